@@ -0,0 +1,4 @@
+#![allow(clippy::upper_case_acronyms)]
+// constructors are called default() throughout, like AABB::default
+#![allow(clippy::should_implement_trait)]
+pub mod utils;
@@ -1,37 +1,53 @@
 extern crate rayon;
 extern crate gif;
-mod utils;
-use utils::camera::Camera;
-use utils::color::Color;
-use utils::dielectric::Dielectric;
-use utils::lambertian::Lambertian;
-use utils::metal::Metal;
-use utils::sphere::Sphere;
-use utils::vec3::Vec3;
-use rand::Rng;
-use utils::aabb::Tree;
+use physics_and_tracing::utils::bench;
+use physics_and_tracing::utils::camera::Camera;
+use physics_and_tracing::utils::camera_path::{CameraPath, Keyframe};
+use physics_and_tracing::utils::color::Color;
+use physics_and_tracing::utils::denoise::Denoiser;
+use physics_and_tracing::utils::gif_output::GifWriter;
+use physics_and_tracing::utils::dielectric::{Dielectric, Ior};
+use physics_and_tracing::utils::heightfield::Heightfield;
+use physics_and_tracing::utils::lambertian::Lambertian;
+use physics_and_tracing::utils::metal::Metal;
+use physics_and_tracing::utils::netpbm::GrayImage;
+use physics_and_tracing::utils::options::Options;
+use physics_and_tracing::utils::sequence;
+use physics_and_tracing::utils::sphere::Sphere;
+use physics_and_tracing::utils::vec3::Vec3;
+use physics_and_tracing::utils::y4m::Y4mWriter;
+use physics_and_tracing::utils::aabb::Tree;
+use physics_and_tracing::utils::float::Float;
 use std::fs::File;
+use std::io::BufWriter;
 use std::sync::Arc;
-use gif::Frame;
 
-fn random_scene() -> Tree {
-    let mut rng = rand::thread_rng();
+fn random_scene(terrain: Option<&GrayImage>) -> Tree {
     let mut world = Tree::new(1);
 
     let ground_mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    let ground_sphere = Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat, Vec3::new(0.0 ,0.0, 0.0));
-
-    world.push(Box::new(ground_sphere));
+    match terrain {
+        Some(image) => {
+            // rolling hills around the spheres, roughly level with y = 0
+            let ground = Heightfield::from_image(image, ground_mat)
+                .with_extent(Vec3::new(-40.0, -1.0, -40.0), 80.0, 80.0, 2.0);
+            world.push(Box::new(ground));
+        }
+        None => {
+            let ground_sphere = Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat, Vec3::new(0.0 ,0.0, 0.0));
+            world.push(Box::new(ground_sphere));
+        }
+    }
 
     // for a in 0..5 {
     //     for b in 0..5 {
-    //         let choose_mat: f64 = rng.gen();
-    //         let center = Vec3::new((a as f64) + rng.gen_range(0.0..0.9),
+    //         let choose_mat: Float = rng.gen();
+    //         let center = Vec3::new((a as Float) + rng.gen_range(0.0..0.9),
     //                                  0.2,
-    //                                  (b as f64) + rng.gen_range(0.0..0.9));
-    //         let movement = Vec3::new((a as f64) + rng.gen_range(0.0..0.9),
+    //                                  (b as Float) + rng.gen_range(0.0..0.9));
+    //         let movement = Vec3::new((a as Float) + rng.gen_range(0.0..0.9),
     //                                  0.2,
-    //                                  (b as f64) + rng.gen_range(0.0..0.9));
+    //                                  (b as Float) + rng.gen_range(0.0..0.9));
 
     //         if choose_mat < 0.8 {
     //             // Diffuse
@@ -64,7 +80,9 @@ fn random_scene() -> Tree {
     //     }
     // }
 
-    let mat1 = Arc::new(Dielectric::new(1.5));
+    // n = 1.5 at the d line, with enough dispersion to split colors when
+    // rendering spectrally
+    let mat1 = Arc::new(Dielectric::dispersive(Ior::Cauchy { a: 1.46, b: 0.0138 }));
     let mat2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
     let mat3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
 
@@ -79,55 +97,142 @@ fn random_scene() -> Tree {
     world
 }
 
+// swings the camera around the scene and in towards the spheres
+fn fly_through(start: Keyframe) -> CameraPath {
+    let orbit = |time: Float, angle: Float, distance: Float, height: Float| {
+        let from = Vec3::new(angle.cos() * distance, height, angle.sin() * distance);
+        Keyframe { time, lookfrom: from, focus_dist: (from - start.lookat).length(), ..start }
+    };
+
+    let angle = start.lookfrom.z().atan2(start.lookfrom.x());
+    let distance = (start.lookfrom.x().powi(2) + start.lookfrom.z().powi(2)).sqrt();
+    CameraPath::new(vec![
+        start,
+        orbit(0.3, angle + 0.3, distance * 0.9, 3.0),
+        orbit(0.6, angle + 0.6, distance * 0.75, 2.5),
+        Keyframe { vfov: start.vfov * 1.3, ..orbit(0.9, angle + 0.8, distance * 0.6, 1.5) },
+    ])
+}
+
 fn main() {
+    let options = match Options::from_args(std::env::args()) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(count) = options.bench {
+        bench::compare_builders(count, options.leaf_size);
+        return;
+    }
+
     // Image
-    const ASPECT_RATIO: f64 = 3.0 / 2.0;
+    const ASPECT_RATIO: Float = 3.0 / 2.0;
     const IMAGE_WIDTH: u64 = 600;
-    const IMAGE_HEIGHT: u64 = ((IMAGE_WIDTH as f64) / ASPECT_RATIO) as u64;
-    const SAMPLES_PER_PIXEL: u64 = 50;
-    const MAX_DEPTH: u64 = 20;
+    const IMAGE_HEIGHT: u64 = ((IMAGE_WIDTH as Float) / ASPECT_RATIO) as u64;
 
     // World
-    let mut world = random_scene();
+    let mut world = random_scene(options.terrain.as_ref());
 
     // Camera
     let lookfrom = Vec3::new(13.0, 2.0, 3.0);
     let lookat = Vec3::new(0.0, 0.0, 0.0);
     let vup = Vec3::new(0.0, 1.0, 0.0);
     let dist_to_focus = 10.0;
-    let aperture = 0.0;
-
-    let cam = Camera::new(lookfrom,
-                          lookat,
-                          vup,
-                          20.0,
-                          ASPECT_RATIO,
-                          aperture,
-                          dist_to_focus,
-                          IMAGE_HEIGHT,
-                          IMAGE_WIDTH,
-                          SAMPLES_PER_PIXEL,
-                          MAX_DEPTH);
-
-    let frames = 10;
+    let aperture = options.aperture;
+
+    let vfov = 20.0;
+
+    let key = Keyframe {
+        time: 0.0,
+        lookfrom,
+        lookat,
+        vup,
+        vfov,
+        focus_dist: dist_to_focus,
+        aperture,
+    };
+
+    let mut path = match options.camera_path {
+        Some(interpolation) => fly_through(key).with_interpolation(interpolation),
+        None => CameraPath::new(vec![key]),
+    };
+    path = path.with_easing(options.easing);
+    if let Some(object_idx) = options.track {
+        if object_idx >= world.len() {
+            eprintln!("--track {} is out of range, the scene has {} objects", object_idx, world.len());
+            std::process::exit(1);
+        }
+        path = path.tracking(object_idx);
+    }
+
     let time_delta = 0.1;
 
     // render
-    let mut file = File::create("results/test.gif").unwrap();
-    let mut encoder = gif::Encoder::new(&mut file, IMAGE_WIDTH as u16, IMAGE_HEIGHT as u16, &[]).unwrap();
-    encoder.set_repeat(gif::Repeat::Infinite).unwrap();
-
-    let mut i = 0;
-    for _ in 0..frames{
-        i += 1;
-        let pixels = cam.render(&world);
-        let frame = Frame::from_rgb(IMAGE_WIDTH as u16, IMAGE_HEIGHT as u16, &pixels);
-        encoder.write_frame(&frame).unwrap();
-        eprint!("\rfinished frame {}          ", i);
-        if i == frames{
+    let file = File::create("results/test.gif").unwrap();
+    let mut gif = GifWriter::new(file, IMAGE_WIDTH as u16, IMAGE_HEIGHT as u16, options.gif);
+    let mut y4m = options.y4m.as_ref().map(|path| {
+        let file = BufWriter::new(File::create(path).unwrap());
+        Y4mWriter::new(file, IMAGE_WIDTH as usize, IMAGE_HEIGHT as usize, options.gif.fps).unwrap()
+    });
+
+    // frames before the selected range are only simulated, not rendered
+    for frame in 0..options.frames.end {
+        if frame >= options.frames.start {
+            let time = (frame as Float) * time_delta;
+
+            let mut key = path.at(time, &world);
+            if let Some((x, y)) = options.autofocus {
+                let u = x / ((IMAGE_WIDTH - 1) as Float);
+                let v = 1.0 - y / ((IMAGE_HEIGHT - 1) as Float);
+                key = key.autofocus(&world, ASPECT_RATIO, u, v);
+            }
+
+            let cam = Camera::new(key.model(options.projection, ASPECT_RATIO, &options.lens),
+                                  IMAGE_HEIGHT,
+                                  IMAGE_WIDTH,
+                                  options.samples_per_pixel,
+                                  options.max_depth)
+                .with_filter(options.filter)
+                .with_spectral(options.spectral)
+                .with_russian_roulette(options.rr_depth);
+
+            let mut film = match options.heatmap {
+                Some(heatmap) => cam.render_heatmap(&world, heatmap),
+                None => cam.render_film(&world),
+            };
+            if options.heatmap.is_none() && (options.denoise || options.aov_dir.is_some()) {
+                let aovs = cam.render_aovs(&world, time_delta);
+                if let Some(dir) = &options.aov_dir {
+                    aovs.write(dir, frame).unwrap();
+                }
+                if options.denoise {
+                    film = Denoiser::default().denoise(&film, &aovs);
+                }
+            }
+
+            if let Some(depth) = options.wireframe {
+                cam.draw_wireframe(&mut film, &world, depth);
+            }
+
+            if let Some(dir) = &options.sequence_dir {
+                sequence::write_frame(dir, frame, options.sequence_format, &film).unwrap();
+            }
+            let pixels = film.to_rgb();
+            if let Some(y4m) = &mut y4m {
+                y4m.write_frame(&pixels).unwrap();
+            }
+            gif.add_frame(pixels).unwrap();
+            eprint!("\rfinished frame {}          ", frame);
+        }
+
+        if frame + 1 == options.frames.end {
             break;
         }
         world = world.step_frame(time_delta);
-        eprint!("\rfinished moving {}         ", i);
+        eprint!("\rfinished moving {}         ", frame);
     }
+    gif.finish().unwrap();
 }
@@ -1,18 +1,23 @@
-// TODO: DOUBLE CHECK IMPL TO MAKE SURE IM NOT MISSING ANY HEIGHT
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use super::minmax::{min, max};
 use super::hit::Hit;
 use super::hit_record::HitRecord;
 use super::ray::Ray;
-use super::sphere::Sphere;
+use rayon::prelude::*;
+
+use super::object::Object;
+use super::sah::{self, BuildNode, TRAVERSAL_COST};
+use super::scatter::Scatter;
 use super::vec3::Vec3;
+use super::float::Float;
 
 const NULL_NODE: usize = 0xffffffff;
 
 #[derive(Clone)]
 pub struct AABB { 
-    pub surface_area: f64,
+    pub surface_area: Float,
     pub close_corner: Vec3,
     pub far_corner: Vec3,
 }
@@ -30,7 +35,7 @@ impl AABB {
         }
     }
 
-    pub fn compute_surface_area(lower: Vec3, upper: Vec3) -> f64 {
+    pub fn compute_surface_area(lower: Vec3, upper: Vec3) -> Float {
         let d = upper - lower;
         2.0 * ((d.x() * d.y()) + (d.x() * d.z()) + (d.y() * d.z()))
     }
@@ -41,21 +46,67 @@ impl AABB {
         AABB::new(close_corner, far_corner)
     }
 
+    pub fn intersects(&self, other: &AABB) -> bool {
+        (0..3).all(|i| {
+            self.close_corner.values[i] <= other.far_corner.values[i]
+                && other.close_corner.values[i] <= self.far_corner.values[i]
+        })
+    }
+
+    pub fn expanded(&self, by: Float) -> AABB {
+        let d = Vec3::new(by, by, by);
+        AABB::new(self.close_corner - d, self.far_corner + d)
+    }
+
+    // zero for points inside
+    pub fn distance_squared(&self, p: Vec3) -> Float {
+        (0..3)
+            .map(|i| {
+                let v = p.values[i];
+                let d = (self.close_corner.values[i] - v).max(v - self.far_corner.values[i]).max(0.0);
+                d * d
+            })
+            .sum()
+    }
+
+    pub fn contains(&self, other: &AABB) -> bool {
+        (0..3).all(|i| {
+            self.close_corner.values[i] <= other.close_corner.values[i]
+                && other.far_corner.values[i] <= self.far_corner.values[i]
+        })
+    }
+
+    // surface area of the region both boxes cover, zero when disjoint
+    pub fn overlap(first: &AABB, second: &AABB) -> Float {
+        let close_corner = Vec3::max(first.close_corner, second.close_corner);
+        let far_corner = Vec3::min(first.far_corner, second.far_corner);
+        if (0..3).any(|i| close_corner.values[i] > far_corner.values[i]) {
+            return 0.0;
+        }
+        AABB::compute_surface_area(close_corner, far_corner)
+    }
+
     // fast slab method
     // outlined here
     // https://tavianator.com/2015/ray_box_nan.html
-    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+    pub fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.clip(r, t_min, t_max).is_some()
+    }
+
+    // the part of [t_min, t_max] the ray spends inside the box
+    pub fn clip(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float)> {
         let mut tmin = t_min;
         let mut tmax = t_max;
 
-        let t1 = (self.close_corner.x() - r.origin().x()) / r.direction().x();
-        let t2 = (self.far_corner.x() - r.origin().x()) / r.direction().x();
+        let inv = r.inv_direction();
+        let t1 = (self.close_corner.x() - r.origin().x()) * inv.x();
+        let t2 = (self.far_corner.x() - r.origin().x()) * inv.x();
         
-        let t3 = (self.close_corner.y() - r.origin().y()) / r.direction().y();
-        let t4 = (self.far_corner.y() - r.origin().y()) / r.direction().y();
+        let t3 = (self.close_corner.y() - r.origin().y()) * inv.y();
+        let t4 = (self.far_corner.y() - r.origin().y()) * inv.y();
 
-        let t5 = (self.close_corner.z() - r.origin().z()) / r.direction().z();
-        let t6 = (self.far_corner.z() - r.origin().z()) / r.direction().z();
+        let t5 = (self.close_corner.z() - r.origin().z()) * inv.z();
+        let t6 = (self.far_corner.z() - r.origin().z()) * inv.z();
 
         tmin = max(tmin, min(t1, t2));
         tmin = max(tmin, min(t3, t4));
@@ -65,7 +116,7 @@ impl AABB {
         tmax = min(tmax, max(t3, t4));
         tmax = min(tmax, max(t5, t6));
 
-        tmax > tmin
+        if tmax > tmin { Some((tmin, tmax)) } else { None }
     }
 }
 
@@ -76,7 +127,9 @@ pub struct Node {
     next: usize,
     left: usize,
     right: usize,
+    // leaves hold leaf_items[particle..particle + count]
     particle: usize,
+    count: usize,
     height: i32,
 }
 
@@ -94,26 +147,49 @@ impl Node {
             left: NULL_NODE,
             right:NULL_NODE,
             particle: NULL_NODE,
+            count: 0,
             height: -1
         }
     }
 }
 
+// summary of a tree's shape for debugging and comparing builders
+#[derive(Clone, Debug)]
+pub struct TreeStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    // edges on the longest path from the root to a leaf
+    pub depth: usize,
+    pub sah_cost: Float,
+    // area shared by sibling boxes relative to the root, lower is better
+    pub overlap: Float,
+}
+
+// work done tracing one ray through the tree
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TraversalCounts {
+    pub box_tests: usize,
+    pub primitive_tests: usize,
+}
+
 pub struct Tree {
     root: usize,
     nodes: Vec<Node>,
     node_count: usize,
     capacity: usize,
     free_list: usize,
-    sphere_map: HashMap<usize, usize>,
-    spheres: Vec<Box<Sphere>>
+    object_map: HashMap<usize, usize>,
+    objects: Vec<Box<dyn Object>>,
+    leaf_items: Vec<usize>,
+    materials: Vec<Arc<dyn Scatter>>,
+    material_ids: Vec<usize>
 }
 
 impl Tree {
-    pub fn from(spheres: Vec<Box<Sphere>>) -> Tree {
-        let mut tree = Tree::new(spheres.len()*2);
-        for sphere in spheres {
-            tree.push(sphere);
+    pub fn from(objects: Vec<Box<dyn Object>>) -> Tree {
+        let mut tree = Tree::new(objects.len()*2);
+        for object in objects {
+            tree.push(object);
         }
         tree
     }
@@ -121,9 +197,9 @@ impl Tree {
     pub fn new(capacity: usize) -> Tree {
         let mut nodes = vec![Node::default(); capacity];
 
-        for i in 0 .. capacity - 1 {
-            nodes[i].next = i + 1;
-            nodes[i].height = -1;
+        for (i, node) in nodes.iter_mut().enumerate().take(capacity - 1) {
+            node.next = i + 1;
+            node.height = -1;
         }
         nodes[capacity - 1].next = NULL_NODE;
         nodes[capacity - 1].height = -1;
@@ -134,8 +210,11 @@ impl Tree {
             node_count: 0,
             capacity,
             free_list: 0,
-            sphere_map: HashMap::new(),
-            spheres: Vec::new()
+            object_map: HashMap::new(),
+            objects: Vec::new(),
+            leaf_items: Vec::new(),
+            materials: Vec::new(),
+            material_ids: Vec::new()
         }
     }
 
@@ -173,12 +252,306 @@ impl Tree {
         self.node_count -= 1;
     }
 
-    pub fn push(&mut self, val: Box<Sphere>) {
-        let idx = self.spheres.len();
-        let lower_bound = val.lower_bound();
-        let upper_bound = val.upper_bound();
-        self.spheres.push(val);
-        self.insert_object(idx, lower_bound, upper_bound)
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    // bulk build for static scenes, a binned sah split gives better trees
+    // than inserting objects one by one
+    pub fn build(objects: Vec<Box<dyn Object>>, leaf_size: usize) -> Tree {
+        let boxes: Vec<AABB> = objects.par_iter().map(|o| o.bounding_box()).collect();
+        let mut tree = Tree::new(2 * objects.len().max(1));
+        for object in objects {
+            tree.add_object(object);
+        }
+        if !boxes.is_empty() {
+            let built = sah::build(&boxes, leaf_size);
+            tree.root = tree.add_built(built, NULL_NODE);
+        }
+        tree
+    }
+
+    fn add_built(&mut self, built: BuildNode, parent: usize) -> usize {
+        let node = self.allocate_node();
+        self.nodes[node].parent = parent;
+        match built {
+            BuildNode::Leaf { aabb, items } => {
+                self.nodes[node].aabb = aabb;
+                self.nodes[node].particle = self.leaf_items.len();
+                self.nodes[node].count = items.len();
+                for &item in &items {
+                    self.object_map.insert(item, node);
+                }
+                self.leaf_items.extend(items);
+            }
+            BuildNode::Interior { aabb, left, right } => {
+                let left = self.add_built(*left, node);
+                let right = self.add_built(*right, node);
+                self.nodes[node].aabb = aabb;
+                self.nodes[node].left = left;
+                self.nodes[node].right = right;
+                self.nodes[node].height =
+                    1 + self.nodes[left].height.max(self.nodes[right].height);
+            }
+        }
+        node
+    }
+
+    pub fn push(&mut self, val: Box<dyn Object>) {
+        let aabb = val.bounding_box();
+        let idx = self.add_object(val);
+        self.insert_object(idx, aabb.close_corner, aabb.far_corner)
+    }
+
+    fn add_object(&mut self, val: Box<dyn Object>) -> usize {
+        let material_id = self.register_material(val.material());
+        self.material_ids.push(material_id);
+        self.objects.push(val);
+        self.objects.len() - 1
+    }
+
+    // expected cost of a random ray against the tree, in object tests
+    pub fn sah_cost(&self) -> Float {
+        if self.root == NULL_NODE {
+            return 0.0;
+        }
+        let root_area = self.nodes[self.root].aabb.surface_area.max(1e-12);
+        let mut cost = 0.0;
+        let mut stack = vec![self.root];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            let weight = node.aabb.surface_area / root_area;
+            if node.is_leaf() {
+                cost += weight * node.count as Float;
+            } else {
+                cost += weight * TRAVERSAL_COST;
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+        cost
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            node_count: self.node_count,
+            leaf_count: 0,
+            depth: 0,
+            sah_cost: self.sah_cost(),
+            overlap: 0.0,
+        };
+        if self.root == NULL_NODE {
+            return stats;
+        }
+        let root_area = self.nodes[self.root].aabb.surface_area.max(1e-12);
+        let mut stack = vec![(self.root, 0)];
+        while let Some((idx, depth)) = stack.pop() {
+            let node = &self.nodes[idx];
+            stats.depth = stats.depth.max(depth);
+            if node.is_leaf() {
+                stats.leaf_count += 1;
+            } else {
+                let (left, right) = (&self.nodes[node.left], &self.nodes[node.right]);
+                stats.overlap += AABB::overlap(&left.aabb, &right.aabb) / root_area;
+                stack.push((node.left, depth + 1));
+                stack.push((node.right, depth + 1));
+            }
+        }
+        stats
+    }
+
+    // boxes of the nodes at a depth below the root, for drawing the tree
+    pub fn boxes_at_depth(&self, depth: usize) -> Vec<AABB> {
+        let mut boxes = Vec::new();
+        if self.root == NULL_NODE {
+            return boxes;
+        }
+        let mut stack = vec![(self.root, 0)];
+        while let Some((idx, d)) = stack.pop() {
+            let node = &self.nodes[idx];
+            if d == depth {
+                boxes.push(node.aabb.clone());
+            } else if !node.is_leaf() {
+                stack.push((node.left, d + 1));
+                stack.push((node.right, d + 1));
+            }
+        }
+        boxes
+    }
+
+    // checks every structural invariant of the tree, describing the first
+    // one that is broken
+    #[cfg(test)]
+    pub fn validate(&self) -> Result<(), String> {
+        use std::collections::HashSet;
+
+        let mut reachable = HashSet::new();
+        let mut seen_items = HashSet::new();
+
+        if self.root != NULL_NODE {
+            if self.nodes[self.root].parent != NULL_NODE {
+                return Err(format!("root {} has parent {}", self.root, self.nodes[self.root].parent));
+            }
+            let mut stack = vec![self.root];
+            while let Some(idx) = stack.pop() {
+                if idx >= self.capacity {
+                    return Err(format!("node {} is past the capacity {}", idx, self.capacity));
+                }
+                if !reachable.insert(idx) {
+                    return Err(format!("node {} is reachable twice", idx));
+                }
+                let node = &self.nodes[idx];
+
+                if node.is_leaf() {
+                    if node.right != NULL_NODE {
+                        return Err(format!("leaf {} has a right child", idx));
+                    }
+                    if node.height != 0 {
+                        return Err(format!("leaf {} has height {}", idx, node.height));
+                    }
+                    if node.count == 0 {
+                        return Err(format!("leaf {} holds no objects", idx));
+                    }
+                    for &item in self.items(idx) {
+                        if item >= self.objects.len() {
+                            return Err(format!("leaf {} holds unknown object {}", idx, item));
+                        }
+                        if !seen_items.insert(item) {
+                            return Err(format!("object {} is in more than one leaf slot", item));
+                        }
+                        if self.object_map.get(&item) != Some(&idx) {
+                            return Err(format!("object {} is in leaf {} but mapped to {:?}", item, idx, self.object_map.get(&item)));
+                        }
+                        if !node.aabb.contains(&self.objects[item].bounding_box()) {
+                            return Err(format!("leaf {} does not contain object {}", idx, item));
+                        }
+                    }
+                    continue;
+                }
+
+                for child in [node.left, node.right] {
+                    if child == NULL_NODE {
+                        return Err(format!("interior node {} is missing a child", idx));
+                    }
+                    let c = &self.nodes[child];
+                    if c.parent != idx {
+                        return Err(format!("node {} is a child of {} but points at parent {}", child, idx, c.parent));
+                    }
+                    if !node.aabb.contains(&c.aabb) {
+                        return Err(format!("node {} does not contain its child {}", idx, child));
+                    }
+                    stack.push(child);
+                }
+                let height = 1 + self.nodes[node.left].height.max(self.nodes[node.right].height);
+                if node.height != height {
+                    return Err(format!("node {} has height {} but its children give {}", idx, node.height, height));
+                }
+            }
+        }
+
+        if reachable.len() != self.node_count {
+            return Err(format!("{} nodes reachable but node_count is {}", reachable.len(), self.node_count));
+        }
+        if self.object_map.len() != seen_items.len() {
+            return Err(format!("{} objects mapped but {} in leaves", self.object_map.len(), seen_items.len()));
+        }
+
+        // every node is either in the tree or on the free list, never both
+        let mut free = 0;
+        let mut idx = self.free_list;
+        while idx != NULL_NODE {
+            if idx >= self.capacity {
+                return Err(format!("free node {} is past the capacity {}", idx, self.capacity));
+            }
+            if reachable.contains(&idx) {
+                return Err(format!("node {} is in the tree and on the free list", idx));
+            }
+            if self.nodes[idx].height != -1 {
+                return Err(format!("free node {} has height {}", idx, self.nodes[idx].height));
+            }
+            free += 1;
+            if free > self.capacity {
+                return Err("free list has a cycle".to_string());
+            }
+            idx = self.nodes[idx].next;
+        }
+        if free + self.node_count != self.capacity {
+            return Err(format!("{} free and {} used nodes but capacity is {}", free, self.node_count, self.capacity));
+        }
+
+        Ok(())
+    }
+
+    // the avl bound insertion keeps, the subtree heights under any node
+    // differ by at most one. Tree::build splits by cost instead so its
+    // trees need not pass
+    #[cfg(test)]
+    pub fn check_balance(&self) -> Result<(), String> {
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if node.height < 1 {
+                continue;
+            }
+            let balance = self.nodes[node.right].height - self.nodes[node.left].height;
+            if balance.abs() > 1 {
+                return Err(format!("node {} is out of balance by {}", idx, balance));
+            }
+        }
+        Ok(())
+    }
+
+    // read only access for compiling the tree into other layouts
+
+    pub fn root(&self) -> Option<usize> {
+        if self.root == NULL_NODE { None } else { Some(self.root) }
+    }
+
+    pub fn node_aabb(&self, node: usize) -> &AABB {
+        &self.nodes[node].aabb
+    }
+
+    // left and right child of an interior node
+    pub fn children(&self, node: usize) -> Option<(usize, usize)> {
+        let n = &self.nodes[node];
+        if n.is_leaf() { None } else { Some((n.left, n.right)) }
+    }
+
+    // objects in a leaf
+    pub fn items(&self, node: usize) -> &[usize] {
+        let n = &self.nodes[node];
+        &self.leaf_items[n.particle..n.particle + n.count]
+    }
+
+    pub fn object(&self, object_idx: usize) -> &dyn Object {
+        self.objects[object_idx].as_ref()
+    }
+
+    // materials shared between objects get the same id
+    fn register_material(&mut self, mat: Arc<dyn Scatter>) -> usize {
+        if let Some(id) = self.materials.iter().position(|m| Arc::ptr_eq(m, &mat)) {
+            return id;
+        }
+        self.materials.push(mat);
+        self.materials.len() - 1
+    }
+
+    pub fn material_id(&self, object_idx: usize) -> usize {
+        self.material_ids[object_idx]
+    }
+
+    pub fn center(&self, object_idx: usize) -> Vec3 {
+        self.objects[object_idx].center()
+    }
+
+    pub fn movement(&self, object_idx: usize) -> Vec3 {
+        self.objects[object_idx].movement()
     }
 
     pub fn insert_object(&mut self, object_idx: usize, lower_bound: Vec3, upper_bound: Vec3){
@@ -193,18 +566,30 @@ impl Tree {
 
         self.insert_leaf(node);
         
-        self.sphere_map.insert(object_idx, node);
+        self.object_map.insert(object_idx, node);
 
-        self.nodes[node].particle = object_idx;
+        self.nodes[node].particle = self.leaf_items.len();
+        self.nodes[node].count = 1;
+        self.leaf_items.push(object_idx);
     }
 
     pub fn remove_object(&mut self, object_idx: usize) {
-        let possible = self.sphere_map.get(&object_idx).cloned();
-        if possible == None { 
+        let node = match self.object_map.get(&object_idx) {
+            Some(&node) => node,
+            None => return,
+        };
+        self.object_map.remove(&object_idx);
+
+        // bulk built leaves can share a node, drop just this object
+        let (start, count) = (self.nodes[node].particle, self.nodes[node].count);
+        if count > 1 {
+            let slot = (start..start + count)
+                .find(|&k| self.leaf_items[k] == object_idx)
+                .unwrap();
+            self.leaf_items.swap(slot, start + count - 1);
+            self.nodes[node].count -= 1;
             return;
         }
-        let node = possible.unwrap();
-        self.sphere_map.remove(&object_idx);
 
         self.remove_leaf(node);
         self.free_node(node);
@@ -458,82 +843,80 @@ impl Tree {
 
             // rotation
             if self.nodes[left_left].height > self.nodes[left_right].height {
-                if self.nodes[left_left].height > self.nodes[left_right].height{
-                    self.nodes[left].right = left_left;
-                    self.nodes[node].left = left_right;
-                    self.nodes[left_right].parent = node;
-
-                    let right_aabb = self.nodes[right].aabb.clone();
-                    let left_right_aabb = self.nodes[left_right].aabb.clone();
-                    self.nodes[node].aabb = AABB::union(&right_aabb, &left_right_aabb);
-                    self.nodes[node].height = 
-                        1 + self.nodes[right].height.max(self.nodes[left_right].height);
-
-                    let node_aabb = self.nodes[node].aabb.clone();
-                    let left_left_aabb = self.nodes[left_left].aabb.clone();
-                    self.nodes[left].aabb = AABB::union(&node_aabb, &left_left_aabb);
-                    self.nodes[left].height = 
-                        1 + self.nodes[node].height.max(self.nodes[left_left].height);
-                }
-                else {
-                    self.nodes[left].right = left_right;
-                    self.nodes[node].left = left_left;
-                    self.nodes[left_left].parent = node;
-
-                    let right_aabb = self.nodes[right].aabb.clone();
-                    let left_left_aabb = self.nodes[left_left].aabb.clone();
-                    self.nodes[node].aabb = AABB::union(&right_aabb, &left_left_aabb);
-                    self.nodes[node].height = 
-                        1 + self.nodes[right].height.max(self.nodes[left_left].height);
-
-                    let node_aabb = self.nodes[node].aabb.clone();
-                    let left_right_aabb = self.nodes[left_right].aabb.clone();
-                    self.nodes[left].aabb = AABB::union(&node_aabb, &left_right_aabb);
-                    self.nodes[left].height =  
-                        1 + self.nodes[node].height.max(self.nodes[left_right].height);
-                }
+                self.nodes[left].right = left_left;
+                self.nodes[node].left = left_right;
+                self.nodes[left_right].parent = node;
+
+                let right_aabb = self.nodes[right].aabb.clone();
+                let left_right_aabb = self.nodes[left_right].aabb.clone();
+                self.nodes[node].aabb = AABB::union(&right_aabb, &left_right_aabb);
+                self.nodes[node].height = 
+                    1 + self.nodes[right].height.max(self.nodes[left_right].height);
 
-                return left;
+                let node_aabb = self.nodes[node].aabb.clone();
+                let left_left_aabb = self.nodes[left_left].aabb.clone();
+                self.nodes[left].aabb = AABB::union(&node_aabb, &left_left_aabb);
+                self.nodes[left].height = 
+                    1 + self.nodes[node].height.max(self.nodes[left_left].height);
             }
+            else {
+                self.nodes[left].right = left_right;
+                self.nodes[node].left = left_left;
+                self.nodes[left_left].parent = node;
+
+                let right_aabb = self.nodes[right].aabb.clone();
+                let left_left_aabb = self.nodes[left_left].aabb.clone();
+                self.nodes[node].aabb = AABB::union(&right_aabb, &left_left_aabb);
+                self.nodes[node].height = 
+                    1 + self.nodes[right].height.max(self.nodes[left_left].height);
+
+                let node_aabb = self.nodes[node].aabb.clone();
+                let left_right_aabb = self.nodes[left_right].aabb.clone();
+                self.nodes[left].aabb = AABB::union(&node_aabb, &left_right_aabb);
+                self.nodes[left].height =  
+                    1 + self.nodes[node].height.max(self.nodes[left_right].height);
+            }
+
+            return left;
         }
 
         node
     }
 
-    pub fn step_frame(&mut self, time_delta: f64) -> Tree{
-        let old_spheres = self.spheres.clone();
-        let new_spheres: Vec<Box<Sphere>> = old_spheres
+    pub fn step_frame(&mut self, time_delta: Float) -> Tree{
+        let new_objects: Vec<Box<dyn Object>> = self.objects
             .iter()
-            .map(|s| Box::new(s.step_frame(time_delta)))
+            .map(|o| o.step_frame(time_delta))
             .collect();
-        Tree::from(new_spheres)
+        Tree::from(new_objects)
     }
 }
 
-impl Hit for Tree {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+impl Tree {
+    // closest hit, also counting the box and object tests it took
+    pub fn hit_counted(&self, r: &Ray, t_min: Float, t_max: Float, counts: &mut TraversalCounts) -> Option<HitRecord<'_>> {
         if self.root == NULL_NODE { return None; }
 
         let mut stack = vec![self.root];
         let mut tmp_rec = None;
         let mut current_closest = t_max;
 
-        while stack.len() > 0 {
-            let top = stack.pop();
-            
-            assert!(top != None);
-
-            let cur_idx = top.unwrap();
+        while let Some(cur_idx) = stack.pop() {
             // we hit this container
             let cur_node = &self.nodes[cur_idx];
+            counts.box_tests += 1;
             if cur_node.aabb.hit(r, t_min, current_closest) {
                 // if its a leaf we check the related object
                 if cur_node.is_leaf() { 
-                    let sphere_idx = cur_node.particle;
-                    let obj = &self.spheres[sphere_idx];
-                    if let Some(rec) = obj.hit(r, t_min, current_closest){
-                       current_closest = rec.t; 
-                       tmp_rec = Some(rec);
+                    let items = &self.leaf_items[cur_node.particle..cur_node.particle + cur_node.count];
+                    for &object_idx in items {
+                        let obj = &self.objects[object_idx];
+                        counts.primitive_tests += 1;
+                        if let Some(mut rec) = obj.hit(r, t_min, current_closest){
+                           current_closest = rec.t; 
+                           rec.object_id = object_idx;
+                           tmp_rec = Some(rec);
+                        }
                     }
                 }
 
@@ -552,3 +935,166 @@ impl Hit for Tree {
         tmp_rec
     }
 }
+
+impl Hit for Tree {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.hit_counted(r, t_min, t_max, &mut TraversalCounts::default())
+    }
+
+    fn occluded(&self, r: &Ray, t_min: Float, t_max: Float) -> bool {
+        if self.root == NULL_NODE { return false; }
+
+        // any order will do, the first object hit ends the search
+        let mut stack = vec![self.root];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if !node.aabb.hit(r, t_min, t_max) {
+                continue;
+            }
+            if node.is_leaf() {
+                let items = &self.leaf_items[node.particle..node.particle + node.count];
+                if items.iter().any(|&i| self.objects[i].occluded(r, t_min, t_max)) {
+                    return true;
+                }
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::Tree;
+    use crate::utils::color::Color;
+    use crate::utils::float::Float;
+    use crate::utils::hit::Hit;
+    use crate::utils::lambertian::Lambertian;
+    use crate::utils::object::Object;
+    use crate::utils::ray::Ray;
+    use crate::utils::sphere::Sphere;
+    use crate::utils::vec3::Vec3;
+
+    fn random_sphere(rng: &mut StdRng) -> Box<dyn Object> {
+        let center = Vec3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Box::new(Sphere::new(center, rng.gen_range(0.1..1.5), mat, Vec3::new(0.0, 0.0, 0.0)))
+    }
+
+    fn random_ray(rng: &mut StdRng) -> Ray {
+        let origin = Vec3::new(rng.gen_range(-15.0..15.0), rng.gen_range(-15.0..15.0), rng.gen_range(-15.0..15.0));
+        let target = Vec3::new(rng.gen_range(-5.0..5.0), rng.gen_range(-5.0..5.0), rng.gen_range(-5.0..5.0));
+        Ray::new(origin, target - origin)
+    }
+
+    // the tree has to find the same closest hit as testing every live object
+    fn check_hits(tree: &Tree, live: &[usize], rng: &mut StdRng) {
+        for _ in 0..20 {
+            let r = random_ray(rng);
+            let expected = live
+                .iter()
+                .filter_map(|&i| tree.object(i).hit(&r, 0.001, Float::INFINITY))
+                .map(|rec| rec.t)
+                .fold(None, |best: Option<Float>, t| Some(best.map_or(t, |b| b.min(t))));
+            assert_eq!(tree.hit(&r, 0.001, Float::INFINITY).map(|rec| rec.t), expected);
+            // any hit up to halfway to the target
+            let blocked = live.iter().any(|&i| tree.object(i).hit(&r, 0.001, 0.5).is_some());
+            assert_eq!(tree.occluded(&r, 0.001, 0.5), blocked);
+        }
+    }
+
+    // random inserts and removals, validating after every step. trees
+    // grown by insertion alone also have to stay balanced
+    fn exercise(mut tree: Tree, mut live: Vec<usize>, seed: u64, steps: usize, balanced: bool) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for step in 0..steps {
+            if live.is_empty() || rng.gen_bool(0.55) {
+                tree.push(random_sphere(&mut rng));
+                live.push(tree.len() - 1);
+            } else {
+                let idx = live.swap_remove(rng.gen_range(0..live.len()));
+                tree.remove_object(idx);
+            }
+            let checked = tree.validate().and_then(|_| if balanced { tree.check_balance() } else { Ok(()) });
+            if let Err(e) = checked {
+                panic!("seed {} step {}: {}", seed, step, e);
+            }
+            if step % 25 == 0 {
+                check_hits(&tree, &live, &mut rng);
+            }
+        }
+        check_hits(&tree, &live, &mut rng);
+        assert_eq!(tree.stats().leaf_count > 0, !live.is_empty());
+    }
+
+    #[test]
+    fn incremental_insert_remove() {
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(1000 + seed);
+            let objects: Vec<Box<dyn Object>> = (0..4).map(|_| random_sphere(&mut rng)).collect();
+            let tree = Tree::from(objects);
+            tree.validate().unwrap();
+            exercise(tree, (0..4).collect(), seed, 300, true);
+        }
+    }
+
+    #[test]
+    fn bulk_built_insert_remove() {
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(2000 + seed);
+            let count = rng.gen_range(1..200);
+            let objects: Vec<Box<dyn Object>> = (0..count).map(|_| random_sphere(&mut rng)).collect();
+            let tree = Tree::build(objects, 4);
+            tree.validate().unwrap();
+            exercise(tree, (0..count).collect(), seed, 300, false);
+        }
+    }
+
+    #[test]
+    fn remove_everything() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let objects: Vec<Box<dyn Object>> = (0..64).map(|_| random_sphere(&mut rng)).collect();
+        let mut tree = Tree::from(objects);
+        for idx in 0..64 {
+            tree.remove_object(idx);
+            tree.validate().unwrap();
+        }
+        assert!(tree.root().is_none());
+        assert_eq!(tree.stats().node_count, 0);
+    }
+
+    #[test]
+    fn stats_on_a_balanced_tree() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let objects: Vec<Box<dyn Object>> = (0..256).map(|_| random_sphere(&mut rng)).collect();
+        let stats = Tree::from(objects).stats();
+        assert_eq!(stats.leaf_count, 256);
+        assert_eq!(stats.node_count, 511);
+        // the rotations keep an avl like height
+        assert!(stats.depth <= 2 * 9, "depth {}", stats.depth);
+        assert!(stats.sah_cost > 0.0 && stats.overlap >= 0.0);
+    }
+
+    // a row by row grid makes the tree left heavy with the deeper side on
+    // the inner grandchild, the double rotation balance() used to skip
+    #[test]
+    fn left_right_rotation() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut tree = Tree::new(1);
+        for i in 0..64 {
+            let center = Vec3::new((i % 8) as Float * 3.0, (i / 8) as Float * 3.0, 0.0);
+            tree.push(Box::new(Sphere::new(center, 1.0, mat.clone(), Vec3::new(0.0, 0.0, 0.0))));
+            if let Err(e) = tree.validate().and_then(|_| tree.check_balance()) {
+                panic!("insert {}: {}", i, e);
+            }
+        }
+        assert_eq!(tree.stats().depth, 7);
+    }
+}
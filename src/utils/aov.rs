@@ -0,0 +1,125 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::color::Color;
+use super::float::Float;
+use super::vec3::Vec3;
+
+// auxiliary buffers captured at the first hit of a ray through each pixel
+// center, stored top row first like the beauty image
+pub struct Aovs {
+    pub width: usize,
+    pub height: usize,
+    pub depth: Vec<Float>,
+    pub normal: Vec<Vec3>,
+    pub albedo: Vec<Color>,
+    pub object_id: Vec<Option<usize>>,
+    pub material_id: Vec<Option<usize>>,
+    pub motion: Vec<(Float, Float)>,
+}
+
+// what a single pixel contributes to the buffers
+pub struct AovSample {
+    pub depth: Float,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub object_id: Option<usize>,
+    pub material_id: Option<usize>,
+    pub motion: (Float, Float),
+}
+
+impl AovSample {
+    // rays that escape to the sky
+    pub fn background() -> AovSample {
+        AovSample {
+            depth: Float::INFINITY,
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            albedo: Color::new(0.0, 0.0, 0.0),
+            object_id: None,
+            material_id: None,
+            motion: (0.0, 0.0),
+        }
+    }
+}
+
+impl Aovs {
+    pub fn from_samples(width: usize, height: usize, samples: Vec<AovSample>) -> Aovs {
+        let mut aovs = Aovs {
+            width,
+            height,
+            depth: Vec::with_capacity(samples.len()),
+            normal: Vec::with_capacity(samples.len()),
+            albedo: Vec::with_capacity(samples.len()),
+            object_id: Vec::with_capacity(samples.len()),
+            material_id: Vec::with_capacity(samples.len()),
+            motion: Vec::with_capacity(samples.len()),
+        };
+
+        for sample in samples {
+            aovs.depth.push(sample.depth);
+            aovs.normal.push(sample.normal);
+            aovs.albedo.push(sample.albedo);
+            aovs.object_id.push(sample.object_id);
+            aovs.material_id.push(sample.material_id);
+            aovs.motion.push(sample.motion);
+        }
+        aovs
+    }
+
+    // writes every buffer as its own pfm file, e.g. depth_0001.pfm
+    pub fn write(&self, dir: &Path, frame: usize) -> io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let name = |pass: &str| dir.join(format!("{}_{:04}.pfm", pass, frame));
+
+        let ids = |ids: &Vec<Option<usize>>| -> Vec<Float> {
+            ids.iter().map(|id| id.map_or(-1.0, |id| id as Float)).collect()
+        };
+
+        write_pfm_gray(&name("depth"), self.width, self.height, &self.depth)?;
+        write_pfm_rgb(&name("normal"), self.width, self.height,
+                      self.normal.iter().map(|n| [n.x(), n.y(), n.z()]))?;
+        write_pfm_rgb(&name("albedo"), self.width, self.height,
+                      self.albedo.iter().map(|c| c.values()))?;
+        write_pfm_gray(&name("object_id"), self.width, self.height, &ids(&self.object_id))?;
+        write_pfm_gray(&name("material_id"), self.width, self.height, &ids(&self.material_id))?;
+        write_pfm_rgb(&name("motion"), self.width, self.height,
+                      self.motion.iter().map(|&(dx, dy)| [dx, dy, 0.0]))?;
+        Ok(())
+    }
+}
+
+// pfm stores little endian floats with the bottom row first
+fn write_pfm_header(out: &mut impl Write, magic: &str, width: usize, height: usize) -> io::Result<()> {
+    write!(out, "{}\n{} {}\n-1.0\n", magic, width, height)
+}
+
+#[cfg_attr(feature = "f32", allow(clippy::unnecessary_cast))]
+pub fn write_pfm_gray(path: &Path, width: usize, height: usize, values: &[Float]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_pfm_header(&mut out, "Pf", width, height)?;
+    for row in values.chunks(width).rev() {
+        for &value in row {
+            out.write_all(&(value as f32).to_le_bytes())?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg_attr(feature = "f32", allow(clippy::unnecessary_cast))]
+pub fn write_pfm_rgb(path: &Path,
+                     width: usize,
+                     height: usize,
+                     values: impl Iterator<Item = [Float; 3]>) -> io::Result<()> {
+    let values: Vec<[Float; 3]> = values.collect();
+    let mut out = BufWriter::new(File::create(path)?);
+    write_pfm_header(&mut out, "PF", width, height)?;
+    for row in values.chunks(width).rev() {
+        for rgb in row {
+            for &value in rgb {
+                out.write_all(&(value as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
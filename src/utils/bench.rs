@@ -0,0 +1,263 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use super::aabb::{Tree, AABB};
+use super::bvh::FlatBvh;
+use super::color::Color;
+use super::float::Float;
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::lambertian::Lambertian;
+use super::object::Object;
+use super::packet::PACKET_WIDTH;
+use super::ray::Ray;
+use super::scatter::Scatter;
+use super::sphere::Sphere;
+use super::vec3::Vec3;
+use super::wide_bvh::{Bvh4, Bvh8};
+
+const RAYS: usize = 200_000;
+
+// spheres spread through a cube that grows with the count, so the density
+// stays about the same for any count
+pub fn random_spheres(count: usize, seed: u64) -> Vec<Sphere> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let side = (count as Float).cbrt() * 2.0;
+    let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    (0..count)
+        .map(|_| {
+            let center = Vec3::new(
+                rng.gen_range(0.0..side),
+                rng.gen_range(0.0..side),
+                rng.gen_range(0.0..side),
+            );
+            Sphere::new(center, rng.gen_range(0.05..0.3), mat.clone(), Vec3::new(0.0, 0.0, 0.0))
+        })
+        .collect()
+}
+
+fn random_rays(count: usize, side: Float, seed: u64) -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| {
+            let origin = Vec3::new(
+                rng.gen_range(0.0..side),
+                rng.gen_range(0.0..side),
+                rng.gen_range(0.0..side),
+            );
+            // rejection sample so the directions come from the seeded rng too
+            let direction = loop {
+                let d = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+                let len = d.length_squared();
+                if len > 1e-6 && len <= 1.0 {
+                    break d.unit_vector();
+                }
+            };
+            Ray::new(origin, direction)
+        })
+        .collect()
+}
+
+fn boxed(spheres: &[Sphere]) -> Vec<Box<dyn Object>> {
+    spheres.iter().map(|s| Box::new(s.clone()) as Box<dyn Object>).collect()
+}
+
+fn trace(tree: &dyn Hit, rays: &[Ray]) -> (Duration, usize) {
+    let start = Instant::now();
+    let hits = rays
+        .iter()
+        .filter(|r| tree.hit(r, 0.001, Float::INFINITY).is_some())
+        .count();
+    (start.elapsed(), hits)
+}
+
+// traces through a compiled copy of the tree, the hit count has to match
+// the tree's own
+fn trace_compiled<'a, T: Hit + 'a>(compile: impl Fn(&'a Tree) -> T, tree: &'a Tree, rays: &[Ray], hits: usize) -> f64 {
+    let compiled = compile(tree);
+    let (time, compiled_hits) = trace(&compiled, rays);
+    assert_eq!(hits, compiled_hits);
+    time.as_secs_f64() * 1000.0
+}
+
+fn report(name: &str, build: Duration, tree: &Tree, rays: &[Ray]) {
+    let (trace_time, hits) = trace(tree, rays);
+    let flat = trace_compiled(FlatBvh::new, tree, rays, hits);
+    let bvh4 = trace_compiled(Bvh4::new, tree, rays, hits);
+    let bvh8 = trace_compiled(Bvh8::new, tree, rays, hits);
+    let stats = tree.stats();
+    println!(
+        "{:<16} {:>10.1} {:>9} {:>6} {:>10.2} {:>8.2} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>8}",
+        name,
+        build.as_secs_f64() * 1000.0,
+        stats.node_count,
+        stats.depth,
+        stats.sah_cost,
+        stats.overlap,
+        trace_time.as_secs_f64() * 1000.0,
+        flat,
+        bvh4,
+        bvh8,
+        hits
+    );
+}
+
+// stands in for hit records that own their material, every object the ray
+// hits clones and drops the arc the whole scene shares
+struct ArcPerHit(Box<dyn Object>);
+
+impl Hit for ArcPerHit {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let rec = self.0.hit(r, t_min, t_max)?;
+        drop(self.0.material());
+        Some(rec)
+    }
+}
+
+impl Object for ArcPerHit {
+    fn bounding_box(&self) -> AABB {
+        self.0.bounding_box()
+    }
+
+    fn step_frame(&self, time_delta: Float) -> Box<dyn Object> {
+        Box::new(ArcPerHit(self.0.step_frame(time_delta)))
+    }
+
+    fn material(&self) -> Arc<dyn Scatter> {
+        self.0.material()
+    }
+
+    fn movement(&self) -> Vec3 {
+        self.0.movement()
+    }
+}
+
+// the same rays split over every thread, where shared state touched per
+// tested object shows up as contention. the borrowed materials against
+// an arc clone per hit object
+fn compare_threads(spheres: &[Sphere], leaf_size: usize, rays: &[Ray]) {
+    let arc_per_hit = boxed(spheres).into_iter().map(|s| Box::new(ArcPerHit(s)) as Box<dyn Object>).collect();
+    let trees = [("borrowed", Tree::build(boxed(spheres), leaf_size)), ("arc per hit", Tree::build(arc_per_hit, leaf_size))];
+    for (name, tree) in &trees {
+        let bvh8 = Bvh8::new(tree);
+        let (single, hits) = trace(&bvh8, rays);
+        let start = Instant::now();
+        let parallel_hits = rays.par_iter().filter(|r| bvh8.hit(r, 0.001, Float::INFINITY).is_some()).count();
+        let parallel = start.elapsed();
+        assert_eq!(hits, parallel_hits);
+        println!(
+            "bvh8, {:<11} on 1 thread {:.1} ms, on {} threads {:.1} ms",
+            name,
+            single.as_secs_f64() * 1000.0,
+            rayon::current_num_threads(),
+            parallel.as_secs_f64() * 1000.0
+        );
+    }
+}
+
+// shadow ray style queries that only ask whether anything is in the way
+// over a limited distance, closest hit against any hit
+fn compare_occlusion(tree: &Tree, rays: &[Ray], distance: Float) {
+    let time = |world: &dyn Hit, any: bool| {
+        let start = Instant::now();
+        let blocked = rays
+            .iter()
+            .filter(|r| {
+                if any {
+                    world.occluded(r, 0.001, distance)
+                } else {
+                    world.hit(r, 0.001, distance).is_some()
+                }
+            })
+            .count();
+        (start.elapsed().as_secs_f64() * 1000.0, blocked)
+    };
+
+    let flat = FlatBvh::new(tree);
+    let bvh8 = Bvh8::new(tree);
+    let worlds: [(&str, &dyn Hit); 3] = [("tree", tree), ("flat", &flat), ("bvh8", &bvh8)];
+    for (name, world) in worlds {
+        let (closest, hits) = time(world, false);
+        let (any, blocked) = time(world, true);
+        assert_eq!(hits, blocked);
+        println!("{:<5} occlusion over {:.1}: closest hit {:.1} ms, any hit {:.1} ms, {} blocked", name, distance, closest, any, blocked);
+    }
+}
+
+// pinhole camera rays looking at the scene from outside one corner, in
+// the order of the tiles they are traced in as packets
+fn camera_rays(side: Float, size: usize) -> Vec<Ray> {
+    let origin = Vec3::new(-0.5 * side, 1.2 * side, -0.5 * side);
+    let forward = (Vec3::new(0.5 * side, 0.5 * side, 0.5 * side) - origin).unit_vector();
+    let right = forward.cross(Vec3::new(0.0, 1.0, 0.0)).unit_vector();
+    let up = right.cross(forward);
+    let mut rays = Vec::with_capacity(size * size);
+    for ty in (0..size).step_by(PACKET_WIDTH) {
+        for tx in (0..size).step_by(PACKET_WIDTH) {
+            for y in ty..(ty + PACKET_WIDTH).min(size) {
+                for x in tx..(tx + PACKET_WIDTH).min(size) {
+                    let u = (x as Float + 0.5) / size as Float - 0.5;
+                    let v = (y as Float + 0.5) / size as Float - 0.5;
+                    rays.push(Ray::new(origin, forward + right * u + up * v));
+                }
+            }
+        }
+    }
+    rays
+}
+
+// traces coherent camera rays one by one and as packets through the same
+// wide bvh, both have to find the same hits
+fn compare_packets(tree: &Tree, side: Float) {
+    let rays = camera_rays(side, 512);
+    let bvh = Bvh8::new(tree);
+    let (single_time, hits) = trace(&bvh, &rays);
+
+    let start = Instant::now();
+    let found: Vec<Option<Float>> = rays
+        .chunks(PACKET_WIDTH * PACKET_WIDTH)
+        .flat_map(|packet| bvh.hit_packet(packet, 0.001, Float::INFINITY))
+        .map(|rec| rec.map(|h| h.t))
+        .collect();
+    let packet_time = start.elapsed();
+    for (r, t) in rays.iter().zip(&found) {
+        assert_eq!(*t, bvh.hit(r, 0.001, Float::INFINITY).map(|h| h.t));
+    }
+    println!(
+        "{} camera rays in {}x{} packets: single {:.1} ms, packets {:.1} ms, {} hits",
+        rays.len(),
+        PACKET_WIDTH,
+        PACKET_WIDTH,
+        single_time.as_secs_f64() * 1000.0,
+        packet_time.as_secs_f64() * 1000.0,
+        hits
+    );
+}
+
+// builds the same random scene incrementally and with the sah builder and
+// traces the same rays through both
+pub fn compare_builders(count: usize, leaf_size: usize) {
+    let spheres = random_spheres(count, 1);
+    let rays = random_rays(RAYS, (count as Float).cbrt() * 2.0, 2);
+    println!("{} spheres, {} rays", count, RAYS);
+    println!(
+        "{:<16} {:>10} {:>9} {:>6} {:>10} {:>8} {:>10} {:>10} {:>10} {:>10} {:>8}",
+        "builder", "build ms", "nodes", "depth", "sah cost", "overlap", "trace ms", "flat ms", "bvh4 ms", "bvh8 ms", "hits"
+    );
+
+    let start = Instant::now();
+    let incremental = Tree::from(boxed(&spheres));
+    report("incremental", start.elapsed(), &incremental, &rays);
+
+    let start = Instant::now();
+    let bulk = Tree::build(boxed(&spheres), leaf_size);
+    report(&format!("sah, leaf {}", leaf_size), start.elapsed(), &bulk, &rays);
+
+    compare_packets(&bulk, (count as Float).cbrt() * 2.0);
+    compare_occlusion(&bulk, &rays, (count as Float).cbrt() * 0.5);
+    compare_threads(&spheres, leaf_size, &rays);
+}
@@ -0,0 +1,244 @@
+use super::aabb::{Tree, AABB};
+use super::float::Float;
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::ray::Ray;
+
+// deepest tree the fixed traversal stack can handle
+const STACK_SIZE: usize = 128;
+
+// bounds are stored as f32, rounded outwards so they stay conservative
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct FlatNode {
+    lower: [f32; 3],
+    upper: [f32; 3],
+    // first item for leaves, index of the second child for interior
+    // nodes since the first child always comes right after its parent
+    offset: u32,
+    // zero for interior nodes
+    count: u16,
+    // axis the children are split along, picks the visiting order
+    axis: u8,
+    _pad: u8,
+}
+
+const _: () = assert!(std::mem::size_of::<FlatNode>() == 32);
+
+impl FlatNode {
+    #[cfg_attr(feature = "f32", allow(clippy::unnecessary_cast))]
+    fn new(aabb: &AABB) -> FlatNode {
+        let c = aabb.close_corner.values;
+        let f = aabb.far_corner.values;
+        FlatNode {
+            lower: [0, 1, 2].map(|i| (c[i] as f32).next_down()),
+            upper: [0, 1, 2].map(|i| (f[i] as f32).next_up()),
+            offset: 0,
+            count: 0,
+            axis: 0,
+            _pad: 0,
+        }
+    }
+
+    // slab test against a precomputed inverse direction
+    #[inline]
+    fn hit(&self, origin: &[Float; 3], inv: &[Float; 3], t_min: Float, t_max: Float) -> bool {
+        let (mut t0, mut t1) = (t_min, t_max);
+        for axis in 0..3 {
+            let a = (self.lower[axis] as Float - origin[axis]) * inv[axis];
+            let b = (self.upper[axis] as Float - origin[axis]) * inv[axis];
+            t0 = t0.max(a.min(b));
+            t1 = t1.min(a.max(b));
+        }
+        t0 <= t1
+    }
+}
+
+// read only copy of a tree laid out depth first for rendering, the tree
+// itself stays the structure that gets updated
+pub struct FlatBvh<'a> {
+    tree: &'a Tree,
+    nodes: Vec<FlatNode>,
+    items: Vec<u32>,
+}
+
+impl<'a> FlatBvh<'a> {
+    pub fn new(tree: &'a Tree) -> FlatBvh<'a> {
+        let mut bvh = FlatBvh {
+            tree,
+            nodes: Vec::with_capacity(tree.node_count()),
+            items: Vec::with_capacity(tree.len()),
+        };
+        if let Some(root) = tree.root() {
+            bvh.flatten(root, 0);
+        }
+        bvh
+    }
+
+    fn flatten(&mut self, node: usize, depth: usize) -> u32 {
+        assert!(depth < STACK_SIZE, "tree too deep to flatten");
+        let idx = self.nodes.len();
+        self.nodes.push(FlatNode::new(self.tree.node_aabb(node)));
+
+        match self.tree.children(node) {
+            None => {
+                let items = self.tree.items(node);
+                self.nodes[idx].offset = self.items.len() as u32;
+                self.nodes[idx].count = u16::try_from(items.len()).expect("leaf too large");
+                self.items.extend(items.iter().map(|&i| i as u32));
+            }
+            Some((left, right)) => {
+                // split along the axis that separates the child centers most
+                let l = self.tree.node_aabb(left);
+                let r = self.tree.node_aabb(right);
+                let gap = (r.close_corner + r.far_corner) - (l.close_corner + l.far_corner);
+                let axis = (0..3)
+                    .max_by(|&a, &b| gap.values[a].abs().total_cmp(&gap.values[b].abs()))
+                    .unwrap();
+                self.nodes[idx].axis = axis as u8;
+                // keep the left child first along the positive axis
+                let (first, second) = if gap.values[axis] >= 0.0 { (left, right) } else { (right, left) };
+                self.flatten(first, depth + 1);
+                self.nodes[idx].offset = self.flatten(second, depth + 1);
+            }
+        }
+        idx as u32
+    }
+}
+
+impl Hit for FlatBvh<'_> {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let origin = r.origin().values;
+        let dir = r.direction().values;
+        let inv = r.inv_direction().values;
+
+        let mut stack = [0u32; STACK_SIZE];
+        let mut sp = 0;
+        let mut idx = 0u32;
+        let mut closest = t_max;
+        let mut best = None;
+        loop {
+            let node = &self.nodes[idx as usize];
+            if node.hit(&origin, &inv, t_min, closest) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for &item in &self.items[start..start + node.count as usize] {
+                        if let Some(mut rec) = self.tree.object(item as usize).hit(r, t_min, closest) {
+                            closest = rec.t;
+                            rec.object_id = item as usize;
+                            best = Some(rec);
+                        }
+                    }
+                } else {
+                    // front to back, the near child along the ray goes first
+                    let (near, far) = if dir[node.axis as usize] < 0.0 {
+                        (node.offset, idx + 1)
+                    } else {
+                        (idx + 1, node.offset)
+                    };
+                    stack[sp] = far;
+                    sp += 1;
+                    idx = near;
+                    continue;
+                }
+            }
+            if sp == 0 {
+                break;
+            }
+            sp -= 1;
+            idx = stack[sp];
+        }
+        best
+    }
+
+    fn occluded(&self, r: &Ray, t_min: Float, t_max: Float) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let origin = r.origin().values;
+        let inv = r.inv_direction().values;
+
+        let mut stack = [0u32; STACK_SIZE];
+        let mut sp = 1;
+        while sp > 0 {
+            sp -= 1;
+            let idx = stack[sp];
+            let node = &self.nodes[idx as usize];
+            if !node.hit(&origin, &inv, t_min, t_max) {
+                continue;
+            }
+            if node.count > 0 {
+                let start = node.offset as usize;
+                let items = &self.items[start..start + node.count as usize];
+                if items.iter().any(|&i| self.tree.object(i as usize).occluded(r, t_min, t_max)) {
+                    return true;
+                }
+            } else {
+                stack[sp] = node.offset;
+                stack[sp + 1] = idx + 1;
+                sp += 2;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::FlatBvh;
+    use crate::utils::aabb::Tree;
+    use crate::utils::color::Color;
+    use crate::utils::float::Float;
+    use crate::utils::hit::Hit;
+    use crate::utils::lambertian::Lambertian;
+    use crate::utils::object::Object;
+    use crate::utils::ray::Ray;
+    use crate::utils::sphere::Sphere;
+    use crate::utils::vec3::Vec3;
+
+    fn random_spheres(count: usize, rng: &mut StdRng) -> Vec<Box<dyn Object>> {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        (0..count)
+            .map(|_| {
+                let center = Vec3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+                Box::new(Sphere::new(center, rng.gen_range(0.1..1.5), mat.clone(), Vec3::new(0.0, 0.0, 0.0))) as Box<dyn Object>
+            })
+            .collect()
+    }
+
+    // the flat copy has to answer exactly like the tree it was made from,
+    // for trees grown by insertion as well as bulk built ones
+    #[test]
+    fn matches_the_tree() {
+        let mut rng = StdRng::seed_from_u64(12);
+        let trees = [
+            Tree::from(random_spheres(300, &mut rng)),
+            Tree::build(random_spheres(300, &mut rng), 4),
+            Tree::build(random_spheres(1, &mut rng), 4),
+            Tree::new(16),
+        ];
+        for tree in &trees {
+            let flat = FlatBvh::new(tree);
+            for _ in 0..2000 {
+                let origin = Vec3::new(rng.gen_range(-15.0..15.0), rng.gen_range(-15.0..15.0), rng.gen_range(-15.0..15.0));
+                let target = Vec3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+                let r = Ray::new(origin, target - origin);
+                let expected = tree.hit(&r, 0.001, Float::INFINITY).map(|rec| (rec.t, rec.object_id));
+                assert_eq!(flat.hit(&r, 0.001, Float::INFINITY).map(|rec| (rec.t, rec.object_id)), expected);
+                for t_max in [0.1, 0.5, 1.0] {
+                    assert_eq!(flat.occluded(&r, 0.001, t_max), tree.occluded(&r, 0.001, t_max));
+                }
+            }
+        }
+    }
+}
@@ -1,86 +1,162 @@
-use super::aabb::Tree;
+use super::aabb::{TraversalCounts, Tree};
+use super::aov::{AovSample, Aovs};
+use super::camera_model::CameraModel;
 use super::ray::Ray;
 use super::vec3::Vec3;
+use super::wide_bvh;
 use super::color::Color;
+use super::debug_view::{self, Heatmap};
+use super::film::{Film, FilmAccumulator};
+use super::filter::Filter;
 use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::medium::MediumStack;
+use super::packet::PACKET_WIDTH;
+use super::spectrum::{rgb_to_spectrum, sample_wavelength, spectral_sample_to_rgb, LAMBDA_D};
+use super::float::Float;
 use rand::Rng;
 use rayon::prelude::*;
 
 pub struct Camera {
-    origin: Vec3,
-    corner: Vec3,
-    horizontal: Vec3,
-    vertical: Vec3,
-    cu: Vec3,
-    cv: Vec3,
-    lens_radius: f64,
+    model: Box<dyn CameraModel>,
     image_height: u64,
     image_width: u64,
     samples_per_pixel: u64,
-    max_depth: u64
+    max_depth: u64,
+    filter: Filter,
+    spectral: bool,
+    rr_depth: u64
 }
 
 impl Camera {
-    pub fn new(lookfrom: Vec3,
-               lookat: Vec3,
-               vup: Vec3,
-               vfov: f64,
-               aspect_ratio: f64,
-               aperture: f64,
-               focus_dist: f64,
+    pub fn new(model: Box<dyn CameraModel>,
                image_height: u64,
                image_width: u64,
                samples_per_pixel: u64,
                max_depth: u64) -> Camera {
-        let theta = std::f64::consts::PI / 180.0 * vfov;
-        let viewport_height: f64 = 2.0 * (theta / 2.0).tan();
-        let viewport_width: f64 = aspect_ratio * viewport_height;
-
-        let cw = (lookfrom - lookat).unit_vector();
-        let cu = vup.cross(cw).unit_vector();
-        let cv = cw.cross(cu);
-
-        let h = cu * viewport_width * focus_dist;
-        let v = cv * viewport_height * focus_dist;
-
-        let llc = lookfrom - h / 2.0 - v / 2.0 - cw * focus_dist;
-
         Camera {
-            origin: lookfrom,
-            corner: llc,
-            horizontal: h,
-            vertical: v,
-            cu,
-            cv,
-            lens_radius: aperture / 2.0,
+            model,
             image_height,
             image_width,
             samples_per_pixel,
-            max_depth
+            max_depth,
+            filter: Filter::default(),
+            spectral: false,
+            rr_depth: 5
         }
     }
 
-    fn get_ray(&self, u: f64, v: f64) -> Ray {
-        let rd = Vec3::random_in_unit_sphere() * self.lens_radius;
-        let offset = self.cu * rd.x() + self.cv * rd.y();
+    pub fn with_filter(mut self, filter: Filter) -> Camera {
+        self.filter = filter;
+        self
+    }
+
+    // trace a single random wavelength per sample instead of rgb
+    pub fn with_spectral(mut self, spectral: bool) -> Camera {
+        self.spectral = spectral;
+        self
+    }
 
-        return Ray::new(
-            self.origin + offset,
-            (self.corner + (self.horizontal * u)) + (self.vertical * v) - self.origin,
-        );
+    // bounces every path gets before russian roulette may end it
+    pub fn with_russian_roulette(mut self, min_depth: u64) -> Camera {
+        self.rr_depth = min_depth;
+        self
     }
 
-    fn ray_color(&self, r: &Ray, world: &Tree, depth: u64) -> Color {
-        if depth <= 0 {
-            return Color::new(0.0, 0.0, 0.0);
+    // maps a world point to pixel coordinates (x right, y down)
+    pub fn project(&self, p: Vec3) -> Option<(Float, Float)> {
+        let (u, v) = self.model.project(p)?;
+        let x = u * ((self.image_width - 1) as Float);
+        let y = (1.0 - v) * ((self.image_height - 1) as Float);
+        Some((x, y))
+    }
+
+    // russian roulette survival chance, capped so bright paths still end
+    fn survival(&self, depth: u64, throughput: Float) -> Float {
+        if depth < self.rr_depth {
+            1.0
+        } else {
+            throughput.min(0.95)
         }
+    }
+
+    // first is the already traced camera hit, the rest of the path is
+    // traced one ray at a time
+    fn ray_color(&self, r: &Ray, world: &dyn Hit, mut first: Option<HitRecord>) -> Color {
+        let mut rng = rand::thread_rng();
+        let mut media = MediumStack::default();
+        let mut ray = *r;
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+
+        for depth in 0..self.max_depth {
+            // bounces are spawned clear of the surface they leave, so the
+            // whole ray is searched
+            let hit = if depth == 0 { first.take() } else { world.hit(&ray, 0.0, Float::INFINITY) };
+            let rec = match hit {
+                Some(rec) => rec,
+                None => return throughput * Self::sky(&ray),
+            };
+
+            // absorption along the segment inside the current medium
+            if let Some(m) = media.current() {
+                throughput = throughput * m.transmittance(rec.t * ray.direction().length());
+            }
+            if let Some(medium) = rec.mat.medium() {
+                ray = media.cross(&ray, &rec, medium, LAMBDA_D);
+            } else if let Some((attentuation, scattered)) = rec.mat.scatter(&ray, &rec) {
+                throughput = throughput * attentuation;
+                ray = scattered;
+            } else {
+                return throughput * Self::sky(&ray);
+            }
 
-        if let Some(rec) = world.hit(r, 0.001, f64::INFINITY) {
-            if let Some((attentuation, scattered)) = rec.mat.scatter(r, &rec) {
-                return self.ray_color(&scattered, world, depth - 1) * attentuation;
+            let [r, g, b] = throughput.values();
+            let p = self.survival(depth, r.max(g).max(b));
+            if rng.gen::<Float>() >= p {
+                break;
             }
+            throughput = throughput / p;
         }
 
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn ray_spectral(&self, r: &Ray, world: &dyn Hit, mut first: Option<HitRecord>, lambda: Float) -> Float {
+        let mut rng = rand::thread_rng();
+        let mut media = MediumStack::default();
+        let mut ray = *r;
+        let mut throughput = 1.0;
+
+        for depth in 0..self.max_depth {
+            let hit = if depth == 0 { first.take() } else { world.hit(&ray, 0.0, Float::INFINITY) };
+            let rec = match hit {
+                Some(rec) => rec,
+                None => return throughput * rgb_to_spectrum(Self::sky(&ray), lambda),
+            };
+
+            if let Some(m) = media.current() {
+                throughput *= m.transmittance_spectral(rec.t * ray.direction().length(), lambda);
+            }
+            if let Some(medium) = rec.mat.medium() {
+                ray = media.cross(&ray, &rec, medium, lambda);
+            } else if let Some((attentuation, scattered)) = rec.mat.scatter_spectral(&ray, &rec, lambda) {
+                throughput *= attentuation;
+                ray = scattered;
+            } else {
+                return throughput * rgb_to_spectrum(Self::sky(&ray), lambda);
+            }
+
+            let p = self.survival(depth, throughput);
+            if rng.gen::<Float>() >= p {
+                break;
+            }
+            throughput /= p;
+        }
+
+        0.0
+    }
+
+    fn sky(r: &Ray) -> Color {
         let unit_direction = r.direction().unit_vector();
         let a = 0.5 * (unit_direction.y() + 1.0);
         Color::new(
@@ -88,36 +164,145 @@ impl Camera {
             (1.0 - a) + (a * 0.7),
             (1.0 - a) + (a * 1.0),
         )
-}
+    }
+
+    fn sample(&self, r: &Ray, world: &dyn Hit, first: Option<HitRecord>) -> Color {
+        if !self.spectral {
+            return self.ray_color(r, world, first);
+        }
+        let (lambda, pdf) = sample_wavelength(rand::thread_rng().gen());
+        let radiance = self.ray_spectral(r, world, first, lambda);
+        spectral_sample_to_rgb(radiance, lambda, pdf)
+    }
+
+    // first hit buffers for compositing, motion is the screen space
+    // offset each hit point moves over the next time_delta
+    pub fn render_aovs(&self, world: &Tree, time_delta: Float) -> Aovs {
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+        let bvh = wide_bvh::compile(world);
+
+        let samples: Vec<AovSample> = (0..width * height).into_par_iter().map(|idx| {
+            let i = idx % width;
+            let j = height - 1 - idx / width;
+
+            let u = (i as Float) / ((self.image_width - 1) as Float);
+            let v = (j as Float) / ((self.image_height - 1) as Float);
+            let r = match self.model.get_center_ray(u, v) {
+                Some(r) => r,
+                None => return AovSample::background(),
+            };
+
+            let rec = match bvh.hit(&r, 0.0, Float::INFINITY) {
+                Some(rec) => rec,
+                None => return AovSample::background(),
+            };
+
+            let moved = rec.p + world.movement(rec.object_id) * time_delta;
+            let motion = match (self.project(rec.p), self.project(moved)) {
+                (Some((x0, y0)), Some((x1, y1))) => (x1 - x0, y1 - y0),
+                _ => (0.0, 0.0),
+            };
+
+            AovSample {
+                depth: self.model.depth(rec.p),
+                normal: rec.normal,
+                albedo: rec.mat.albedo(),
+                object_id: Some(rec.object_id),
+                material_id: Some(world.material_id(rec.object_id)),
+                motion,
+            }
+        }).collect();
+
+        Aovs::from_samples(width, height, samples)
+    }
+
+    // false color count of the work the tree does for the ray through each
+    // pixel center, instead of shading
+    pub fn render_heatmap(&self, world: &Tree, heatmap: Heatmap) -> Film {
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+
+        let counts: Vec<usize> = (0..width * height).into_par_iter().map(|idx| {
+            let i = idx % width;
+            let j = height - 1 - idx / width;
+
+            let u = (i as Float) / ((self.image_width - 1) as Float);
+            let v = (j as Float) / ((self.image_height - 1) as Float);
+            let mut counts = TraversalCounts::default();
+            if let Some(r) = self.model.get_center_ray(u, v) {
+                world.hit_counted(&r, 0.0, Float::INFINITY, &mut counts);
+            }
+            heatmap.count(&counts)
+        }).collect();
+
+        debug_view::heatmap_film(width, height, &counts)
+    }
+
+    // outlines the boxes of the tree nodes at a depth on top of the film
+    pub fn draw_wireframe(&self, film: &mut Film, world: &Tree, depth: usize) {
+        let color = Color::new(0.1, 1.0, 0.2);
+        let project = |p: Vec3| self.project(p);
+        for aabb in world.boxes_at_depth(depth) {
+            debug_view::draw_box(film, &aabb, &project, color);
+        }
+    }
 
     pub fn render(&self, world: &Tree) -> Vec<u8>{
-        let mut full_image: Vec<u8> = Vec::new();
-        for j in (0..self.image_height).rev() {
-            let scanline: Vec<Color> = (0..self.image_width).into_par_iter().map(|i| {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                for _ in 0..self.samples_per_pixel {
-                    let mut rng = rand::thread_rng();
-                    let random_u: f64 = rng.gen();
-                    let random_v: f64 = rng.gen();
-
-                    let u = ((i as f64) + random_u) / ((self.image_width - 1) as f64);
-                    let v = ((j as f64) + random_v) / ((self.image_height - 1) as f64);
-                    let r = self.get_ray(u, v);
-                    pixel_color += self.ray_color(&r, &world, self.max_depth);
+        self.render_film(world).to_rgb()
+    }
+
+    pub fn render_film(&self, world: &Tree) -> Film {
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+        // the tree is compiled once per frame into a layout built for tracing
+        let bvh = wide_bvh::compile(world);
+
+        // each worker splats into its own accumulator, merged at the end.
+        // work is split into bands of tiles whose camera rays are traced
+        // together as one packet per sample
+        let accumulator = (0..height.div_ceil(PACKET_WIDTH)).into_par_iter().fold(
+            || FilmAccumulator::new(width, height),
+            |mut acc, band| {
+                let mut rng = rand::thread_rng();
+                let rows = band * PACKET_WIDTH..((band + 1) * PACKET_WIDTH).min(height);
+                for tile in (0..width).step_by(PACKET_WIDTH) {
+                    let columns = tile..(tile + PACKET_WIDTH).min(width);
+                    for _ in 0..self.samples_per_pixel {
+                        let mut rays = Vec::with_capacity(PACKET_WIDTH * PACKET_WIDTH);
+                        let mut positions = Vec::with_capacity(PACKET_WIDTH * PACKET_WIDTH);
+                        for j in rows.clone() {
+                            for i in columns.clone() {
+                                let random_u: Float = rng.gen();
+                                let random_v: Float = rng.gen();
+
+                                let u = ((i as Float) + random_u) / ((self.image_width - 1) as Float);
+                                let v = ((j as Float) + random_v) / ((self.image_height - 1) as Float);
+                                // raster space has y pointing down
+                                let x = (i as Float) + random_u;
+                                let y = ((height - 1 - j) as Float) + (1.0 - random_v);
+                                // samples outside the projection still count as black
+                                match self.model.get_ray(u, v) {
+                                    Some(r) => {
+                                        rays.push(r);
+                                        positions.push((x, y));
+                                    }
+                                    None => acc.splat(x, y, Color::new(0.0, 0.0, 0.0), &self.filter),
+                                }
+                            }
+                        }
+
+                        let hits = bvh.hit_packet(&rays, 0.0, Float::INFINITY);
+                        for ((r, (x, y)), first) in rays.iter().zip(positions).zip(hits) {
+                            let color = self.sample(r, bvh.as_ref(), first);
+                            acc.splat(x, y, color, &self.filter);
+                        }
+                    }
                 }
-                pixel_color
-            }).collect();
-
-            let flattened: Vec<u8> = scanline
-                              .iter()
-                              .flat_map(|color| vec![
-                                color.r(self.samples_per_pixel),
-                                color.g(self.samples_per_pixel),
-                                color.b(self.samples_per_pixel)
-                              ])
-                              .collect();
-            full_image.extend(flattened);
-        }
-        full_image
+                acc
+            },
+        ).reduce(|| FilmAccumulator::new(width, height), FilmAccumulator::merge);
+
+        accumulator.resolve()
     }
 }
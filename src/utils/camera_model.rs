@@ -0,0 +1,406 @@
+
+use super::float::{consts::PI, Float};
+use super::lens::Lens;
+use super::ray::Ray;
+use super::vec3::Vec3;
+
+// maps normalized image coordinates to rays and back again,
+// u goes left to right and v bottom to top, both in [0, 1]
+pub trait CameraModel: Send + Sync {
+    // None when (u, v) falls outside the projection, e.g. past a fisheye circle
+    fn get_ray(&self, u: Float, v: Float) -> Option<Ray>;
+
+    // ray without any lens sampling, used for the aovs
+    fn get_center_ray(&self, u: Float, v: Float) -> Option<Ray> {
+        self.get_ray(u, v)
+    }
+
+    // inverse of get_center_ray
+    fn project(&self, p: Vec3) -> Option<(Float, Float)>;
+
+    // distance used for the depth aov
+    fn depth(&self, p: Vec3) -> Float;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Fisheye(FisheyeMapping),
+    Equirectangular,
+    Cylindrical,
+}
+
+impl Projection {
+    pub fn from_name(name: &str) -> Option<Projection> {
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic),
+            "fisheye" => Some(Projection::Fisheye(FisheyeMapping::Equidistant)),
+            "fisheye-equisolid" => Some(Projection::Fisheye(FisheyeMapping::Equisolid)),
+            "equirectangular" => Some(Projection::Equirectangular),
+            "cylindrical" => Some(Projection::Cylindrical),
+            _ => None,
+        }
+    }
+}
+
+// orthonormal camera frame, cw points backwards like in the book
+#[derive(Clone, Copy)]
+pub struct Basis {
+    pub origin: Vec3,
+    pub cu: Vec3,
+    pub cv: Vec3,
+    pub cw: Vec3,
+}
+
+impl Basis {
+    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3) -> Basis {
+        let cw = (lookfrom - lookat).unit_vector();
+        let cu = vup.cross(cw).unit_vector();
+        let cv = cw.cross(cu);
+        Basis { origin: lookfrom, cu, cv, cw }
+    }
+
+    pub fn to_world(self, x: Float, y: Float, z: Float) -> Vec3 {
+        self.cu * x + self.cv * y - self.cw * z
+    }
+
+    // camera space with x right, y up and z forward
+    pub fn to_local(self, p: Vec3) -> Vec3 {
+        let d = p - self.origin;
+        Vec3::new(d.dot(self.cu), d.dot(self.cv), -d.dot(self.cw))
+    }
+}
+
+// thin lens perspective camera
+pub struct Perspective {
+    basis: Basis,
+    corner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    lens_radius: Float,
+    lens: Lens,
+}
+
+impl Perspective {
+    pub fn new(lookfrom: Vec3,
+               lookat: Vec3,
+               vup: Vec3,
+               vfov: Float,
+               aspect_ratio: Float,
+               aperture: Float,
+               focus_dist: Float) -> Perspective {
+        let theta = PI / 180.0 * vfov;
+        let viewport_height: Float = 2.0 * (theta / 2.0).tan();
+        let viewport_width: Float = aspect_ratio * viewport_height;
+
+        let basis = Basis::new(lookfrom, lookat, vup);
+
+        let h = basis.cu * viewport_width * focus_dist;
+        let v = basis.cv * viewport_height * focus_dist;
+
+        let llc = lookfrom - h / 2.0 - v / 2.0 - basis.cw * focus_dist;
+
+        Perspective {
+            basis,
+            corner: llc,
+            horizontal: h,
+            vertical: v,
+            lens_radius: aperture / 2.0,
+            lens: Lens::default(),
+        }
+    }
+
+    pub fn with_lens(mut self, lens: Lens) -> Perspective {
+        self.lens = lens;
+        self
+    }
+}
+
+impl CameraModel for Perspective {
+    fn get_ray(&self, u: Float, v: Float) -> Option<Ray> {
+        if self.lens_radius <= 0.0 {
+            return self.get_center_ray(u, v);
+        }
+
+        let (lx, ly) = self.lens.sample(2.0 * u - 1.0, 2.0 * v - 1.0)?;
+        let offset = (self.basis.cu * lx + self.basis.cv * ly) * self.lens_radius;
+
+        // every lens sample converges on the same point of the focus plane
+        let target = (self.corner + (self.horizontal * u)) + (self.vertical * v);
+        Some(Ray::new(
+            self.basis.origin + offset,
+            target - self.basis.origin - offset,
+        ))
+    }
+
+    fn get_center_ray(&self, u: Float, v: Float) -> Option<Ray> {
+        Some(Ray::new(
+            self.basis.origin,
+            (self.corner + (self.horizontal * u)) + (self.vertical * v) - self.basis.origin,
+        ))
+    }
+
+    fn project(&self, p: Vec3) -> Option<(Float, Float)> {
+        let d = p - self.basis.origin;
+        let depth = self.depth(p);
+        if depth <= 0.0 {
+            return None;
+        }
+
+        // intersect with the focus plane the viewport lives on
+        let focus_dist = (self.basis.origin - self.corner).dot(self.basis.cw);
+        let on_plane = self.basis.origin + d * (focus_dist / depth) - self.corner;
+        let u = on_plane.dot(self.basis.cu) / self.horizontal.length();
+        let v = on_plane.dot(self.basis.cv) / self.vertical.length();
+        Some((u, v))
+    }
+
+    fn depth(&self, p: Vec3) -> Float {
+        -(p - self.basis.origin).dot(self.basis.cw)
+    }
+}
+
+// parallel rays, view_height is the world space height of the image
+pub struct Orthographic {
+    basis: Basis,
+    width: Float,
+    height: Float,
+}
+
+impl Orthographic {
+    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, view_height: Float, aspect_ratio: Float) -> Orthographic {
+        Orthographic {
+            basis: Basis::new(lookfrom, lookat, vup),
+            width: view_height * aspect_ratio,
+            height: view_height,
+        }
+    }
+}
+
+impl CameraModel for Orthographic {
+    fn get_ray(&self, u: Float, v: Float) -> Option<Ray> {
+        let origin = self.basis.origin
+            + self.basis.to_world((u - 0.5) * self.width, (v - 0.5) * self.height, 0.0);
+        Some(Ray::new(origin, self.basis.cw * -1.0))
+    }
+
+    fn project(&self, p: Vec3) -> Option<(Float, Float)> {
+        let local = self.basis.to_local(p);
+        Some((local.x() / self.width + 0.5, local.y() / self.height + 0.5))
+    }
+
+    fn depth(&self, p: Vec3) -> Float {
+        self.basis.to_local(p).z()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FisheyeMapping {
+    // image radius grows linearly with the angle off axis
+    Equidistant,
+    // equal solid angles cover equal image areas
+    Equisolid,
+}
+
+// the image circle touches the top and bottom of the frame,
+// fov is the full angle it covers in degrees (up to 360)
+pub struct Fisheye {
+    basis: Basis,
+    mapping: FisheyeMapping,
+    half_fov: Float,
+    aspect_ratio: Float,
+}
+
+impl Fisheye {
+    pub fn new(lookfrom: Vec3,
+               lookat: Vec3,
+               vup: Vec3,
+               fov: Float,
+               aspect_ratio: Float,
+               mapping: FisheyeMapping) -> Fisheye {
+        Fisheye {
+            basis: Basis::new(lookfrom, lookat, vup),
+            mapping,
+            half_fov: (fov / 2.0).to_radians().min(PI),
+            aspect_ratio,
+        }
+    }
+
+    fn angle_at(&self, r: Float) -> Option<Float> {
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.half_fov,
+            FisheyeMapping::Equisolid => {
+                let s = r * (self.half_fov / 2.0).sin();
+                if s > 1.0 {
+                    return None;
+                }
+                2.0 * s.asin()
+            }
+        };
+        Some(theta)
+    }
+
+    fn radius_at(&self, theta: Float) -> Float {
+        match self.mapping {
+            FisheyeMapping::Equidistant => theta / self.half_fov,
+            FisheyeMapping::Equisolid => (theta / 2.0).sin() / (self.half_fov / 2.0).sin(),
+        }
+    }
+}
+
+impl CameraModel for Fisheye {
+    fn get_ray(&self, u: Float, v: Float) -> Option<Ray> {
+        let x = (u - 0.5) * 2.0 * self.aspect_ratio;
+        let y = (v - 0.5) * 2.0;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = self.angle_at(r)?;
+        let phi = y.atan2(x);
+        let dir = self.basis.to_world(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+        Some(Ray::new(self.basis.origin, dir))
+    }
+
+    fn project(&self, p: Vec3) -> Option<(Float, Float)> {
+        let local = self.basis.to_local(p);
+        let length = local.length();
+        if length == 0.0 {
+            return None;
+        }
+
+        // rays on the rim of the image circle round either way
+        let theta = (local.z() / length).clamp(-1.0, 1.0).acos();
+        if theta > self.half_fov + Float::EPSILON.sqrt() {
+            return None;
+        }
+        let r = self.radius_at(theta);
+        let phi = local.y().atan2(local.x());
+        Some((r * phi.cos() / (2.0 * self.aspect_ratio) + 0.5, r * phi.sin() / 2.0 + 0.5))
+    }
+
+    fn depth(&self, p: Vec3) -> Float {
+        (p - self.basis.origin).length()
+    }
+}
+
+// full 360 by 180 degree latitude-longitude panorama, the usual
+// layout for environment maps and vr stills
+pub struct Equirectangular {
+    basis: Basis,
+}
+
+impl Equirectangular {
+    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3) -> Equirectangular {
+        Equirectangular { basis: Basis::new(lookfrom, lookat, vup) }
+    }
+}
+
+impl CameraModel for Equirectangular {
+    fn get_ray(&self, u: Float, v: Float) -> Option<Ray> {
+        let longitude = (u - 0.5) * 2.0 * PI;
+        let latitude = (v - 0.5) * PI;
+        let dir = self.basis.to_world(latitude.cos() * longitude.sin(),
+                                      latitude.sin(),
+                                      latitude.cos() * longitude.cos());
+        Some(Ray::new(self.basis.origin, dir))
+    }
+
+    fn project(&self, p: Vec3) -> Option<(Float, Float)> {
+        let local = self.basis.to_local(p);
+        let length = local.length();
+        if length == 0.0 {
+            return None;
+        }
+
+        let longitude = local.x().atan2(local.z());
+        let latitude = (local.y() / length).clamp(-1.0, 1.0).asin();
+        Some((longitude / (2.0 * PI) + 0.5, latitude / PI + 0.5))
+    }
+
+    fn depth(&self, p: Vec3) -> Float {
+        (p - self.basis.origin).length()
+    }
+}
+
+// angular horizontally and perspective vertically, hfov in degrees
+pub struct Cylindrical {
+    basis: Basis,
+    hfov: Float,
+    view_height: Float,
+}
+
+impl Cylindrical {
+    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, hfov: Float, vfov: Float) -> Cylindrical {
+        Cylindrical {
+            basis: Basis::new(lookfrom, lookat, vup),
+            hfov: hfov.to_radians(),
+            view_height: 2.0 * (vfov.to_radians() / 2.0).tan(),
+        }
+    }
+}
+
+impl CameraModel for Cylindrical {
+    fn get_ray(&self, u: Float, v: Float) -> Option<Ray> {
+        let angle = (u - 0.5) * self.hfov;
+        let dir = self.basis.to_world(angle.sin(), (v - 0.5) * self.view_height, angle.cos());
+        Some(Ray::new(self.basis.origin, dir))
+    }
+
+    fn project(&self, p: Vec3) -> Option<(Float, Float)> {
+        let local = self.basis.to_local(p);
+        let radius = (local.x() * local.x() + local.z() * local.z()).sqrt();
+        if radius == 0.0 {
+            return None;
+        }
+
+        let angle = local.x().atan2(local.z());
+        Some((angle / self.hfov + 0.5, local.y() / radius / self.view_height + 0.5))
+    }
+
+    fn depth(&self, p: Vec3) -> Float {
+        let local = self.basis.to_local(p);
+        (local.x() * local.x() + local.z() * local.z()).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CameraModel, Cylindrical, Equirectangular, Fisheye, FisheyeMapping, Orthographic, Perspective};
+    use crate::utils::float::Float;
+    use crate::utils::vec3::Vec3;
+
+    // a point anywhere along the center ray has to project back onto the
+    // image position the ray came from
+    #[test]
+    fn project_inverts_center_rays() {
+        let (from, at, up) = (Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let models: [(&str, Box<dyn CameraModel>); 6] = [
+            ("perspective", Box::new(Perspective::new(from, at, up, 20.0, 1.5, 0.0, 10.0))),
+            ("orthographic", Box::new(Orthographic::new(from, at, up, 4.0, 1.5))),
+            ("fisheye", Box::new(Fisheye::new(from, at, up, 180.0, 1.5, FisheyeMapping::Equidistant))),
+            ("fisheye-equisolid", Box::new(Fisheye::new(from, at, up, 180.0, 1.5, FisheyeMapping::Equisolid))),
+            ("equirectangular", Box::new(Equirectangular::new(from, at, up))),
+            ("cylindrical", Box::new(Cylindrical::new(from, at, up, 120.0, 40.0))),
+        ];
+        for (name, model) in &models {
+            let mut rays = 0;
+            for j in 1..20 {
+                for i in 1..20 {
+                    let (u, v) = (i as Float / 20.0, j as Float / 20.0);
+                    // outside the fisheye circle there is no ray
+                    let Some(r) = model.get_center_ray(u, v) else { continue };
+                    rays += 1;
+                    let p = r.at(3.7);
+                    let (pu, pv) = model.project(p).unwrap_or_else(|| panic!("{} lost ({}, {})", name, u, v));
+                    assert!((pu - u).abs() < 1e-4 && (pv - v).abs() < 1e-4, "{} ({}, {}) came back as ({}, {})", name, u, v, pu, pv);
+                    assert!(model.depth(p) > 0.0, "{}", name);
+                }
+            }
+            assert!(rays > 100, "{} only gave {} rays", name, rays);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use super::aabb::Tree;
+use super::hit::Hit;
+use super::lens::Lens;
+use super::camera_model::{
+    CameraModel, Cylindrical, Equirectangular, Fisheye, Orthographic, Perspective, Projection,
+};
+use super::vec3::Vec3;
+use super::float::Float;
+
+// full camera placement at a point in time
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: Float,
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub vup: Vec3,
+    pub vfov: Float,
+    pub focus_dist: Float,
+    pub aperture: Float,
+}
+
+// field of view the non perspective fisheye covers
+const FISHEYE_FOV: Float = 180.0;
+
+impl Keyframe {
+    pub fn model(&self, projection: Projection, aspect_ratio: Float, lens: &Lens) -> Box<dyn CameraModel> {
+        let (lookfrom, lookat, vup) = (self.lookfrom, self.lookat, self.vup);
+        match projection {
+            Projection::Perspective => Box::new(Perspective::new(
+                lookfrom, lookat, vup, self.vfov, aspect_ratio, self.aperture, self.focus_dist)
+                .with_lens(lens.clone())),
+            // frame the focus plane the same way the perspective camera would
+            Projection::Orthographic => {
+                let view_height = 2.0 * self.focus_dist * (self.vfov.to_radians() / 2.0).tan();
+                Box::new(Orthographic::new(lookfrom, lookat, vup, view_height, aspect_ratio))
+            }
+            Projection::Fisheye(mapping) => Box::new(
+                Fisheye::new(lookfrom, lookat, vup, FISHEYE_FOV, aspect_ratio, mapping)),
+            Projection::Equirectangular => Box::new(
+                Equirectangular::new(lookfrom, lookat, vup)),
+            Projection::Cylindrical => Box::new(
+                Cylindrical::new(lookfrom, lookat, vup, self.vfov * aspect_ratio, self.vfov)),
+        }
+    }
+
+    // focuses on whatever a probe ray through (u, v) hits first,
+    // keeps the current distance if it escapes the scene
+    pub fn autofocus(&self, world: &Tree, aspect_ratio: Float, u: Float, v: Float) -> Keyframe {
+        let probe = Perspective::new(
+            self.lookfrom, self.lookat, self.vup, self.vfov, aspect_ratio, 0.0, 1.0);
+        let focus_dist = probe
+            .get_center_ray(u, v)
+            .and_then(|r| world.hit(&r, 0.0, Float::INFINITY))
+            .map(|rec| probe.depth(rec.p))
+            .unwrap_or(self.focus_dist);
+        Keyframe { focus_dist, ..*self }
+    }
+
+    fn lerp(&self, other: &Keyframe, t: Float) -> Keyframe {
+        let mix = |a: Float, b: Float| a + (b - a) * t;
+        Keyframe {
+            time: mix(self.time, other.time),
+            lookfrom: self.lookfrom + (other.lookfrom - self.lookfrom) * t,
+            lookat: self.lookat + (other.lookat - self.lookat) * t,
+            vup: self.vup + (other.vup - self.vup) * t,
+            vfov: mix(self.vfov, other.vfov),
+            focus_dist: mix(self.focus_dist, other.focus_dist),
+            aperture: mix(self.aperture, other.aperture),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interpolation {
+    Linear,
+    CatmullRom,
+}
+
+// reshapes the progress within each segment between two keys
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Easing {
+    None,
+    In,
+    Out,
+    InOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: Float) -> Float {
+        match self {
+            Easing::None => t,
+            Easing::In => t * t,
+            Easing::Out => t * (2.0 - t),
+            Easing::InOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+pub struct CameraPath {
+    keys: Vec<Keyframe>,
+    interpolation: Interpolation,
+    easing: Easing,
+    // object in the tree the camera keeps looking at
+    target: Option<usize>,
+}
+
+impl CameraPath {
+    pub fn new(mut keys: Vec<Keyframe>) -> CameraPath {
+        assert!(!keys.is_empty());
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        CameraPath {
+            keys,
+            interpolation: Interpolation::Linear,
+            easing: Easing::None,
+            target: None,
+        }
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> CameraPath {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> CameraPath {
+        self.easing = easing;
+        self
+    }
+
+    // overrides the keyframed lookat with the center of a moving object
+    pub fn tracking(mut self, object_idx: usize) -> CameraPath {
+        self.target = Some(object_idx);
+        self
+    }
+
+    pub fn at(&self, time: Float, world: &Tree) -> Keyframe {
+        let mut key = self.interpolate(time);
+        if let Some(object_idx) = self.target {
+            key.lookat = world.center(object_idx);
+        }
+        key.vup = key.vup.unit_vector();
+        key
+    }
+
+    fn interpolate(&self, time: Float) -> Keyframe {
+        let last = self.keys.len() - 1;
+        if time <= self.keys[0].time {
+            return Keyframe { time, ..self.keys[0] };
+        }
+        if time >= self.keys[last].time {
+            return Keyframe { time, ..self.keys[last] };
+        }
+
+        // segment [i, i + 1] containing time
+        let i = self.keys.iter().rposition(|k| k.time <= time).unwrap();
+        let (k1, k2) = (&self.keys[i], &self.keys[i + 1]);
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 { (time - k1.time) / span } else { 0.0 };
+        let t = self.easing.apply(t);
+
+        let key = match self.interpolation {
+            Interpolation::Linear => k1.lerp(k2, t),
+            Interpolation::CatmullRom => {
+                // clamp the outer control points at the ends of the path
+                let k0 = &self.keys[i.saturating_sub(1)];
+                let k3 = &self.keys[(i + 2).min(last)];
+                Keyframe {
+                    time,
+                    lookfrom: catmull_rom_vec(k0.lookfrom, k1.lookfrom, k2.lookfrom, k3.lookfrom, t),
+                    lookat: catmull_rom_vec(k0.lookat, k1.lookat, k2.lookat, k3.lookat, t),
+                    vup: catmull_rom_vec(k0.vup, k1.vup, k2.vup, k3.vup, t),
+                    vfov: catmull_rom(k0.vfov, k1.vfov, k2.vfov, k3.vfov, t),
+                    focus_dist: catmull_rom(k0.focus_dist, k1.focus_dist, k2.focus_dist, k3.focus_dist, t),
+                    aperture: catmull_rom(k0.aperture, k1.aperture, k2.aperture, k3.aperture, t).max(0.0),
+                }
+            }
+        };
+        Keyframe { time, ..key }
+    }
+}
+
+// uniform catmull-rom spline through p1 and p2
+fn catmull_rom(p0: Float, p1: Float, p2: Float, p3: Float, t: Float) -> Float {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * ((2.0 * p1)
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+fn catmull_rom_vec(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: Float) -> Vec3 {
+    Vec3::new(catmull_rom(p0.x(), p1.x(), p2.x(), p3.x(), t),
+              catmull_rom(p0.y(), p1.y(), p2.y(), p3.y(), t),
+              catmull_rom(p0.z(), p1.z(), p2.z(), p3.z(), t))
+}
@@ -1,26 +1,35 @@
-use std::ops::{AddAssign, Mul};
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+use super::float::Float;
 
 #[derive(Copy, Clone)]
 pub struct Color {
-    values: [f64; 3],
+    values: [Float; 3],
 }
 
 impl Color {
-    pub fn new(r: f64, g: f64, b: f64) -> Self {
+    pub fn new(r: Float, g: Float, b: Float) -> Self {
         Color { values: [r, g, b] }
     }
 
+    pub fn values(&self) -> [Float; 3] {
+        self.values
+    }
+
+    pub fn length_squared(&self) -> Float {
+        self.values.iter().map(|v| v * v).sum()
+    }
+
     pub fn format_color(self, samples_per_pixel: u64) -> String {
         let ir = (256.0
-            * (self.values[0] / (samples_per_pixel as f64))
+            * (self.values[0] / (samples_per_pixel as Float))
                 .sqrt()
                 .clamp(0.0, 0.999)) as u64;
         let ig = (256.0
-            * (self.values[1] / (samples_per_pixel as f64))
+            * (self.values[1] / (samples_per_pixel as Float))
                 .sqrt()
                 .clamp(0.0, 0.999)) as u64;
         let ib = (256.0
-            * (self.values[2] / (samples_per_pixel as f64))
+            * (self.values[2] / (samples_per_pixel as Float))
                 .sqrt()
                 .clamp(0.0, 0.999)) as u64;
 
@@ -29,7 +38,7 @@ impl Color {
 
     pub fn r(self, samples_per_pixel: u64) -> u8 {
         let ir: u8 = (256.0
-            * (self.values[0] / (samples_per_pixel as f64))
+            * (self.values[0] / (samples_per_pixel as Float))
                 .sqrt()
                 .clamp(0.0, 0.999)) as u8;
         ir
@@ -37,7 +46,7 @@ impl Color {
 
     pub fn g(self, samples_per_pixel: u64) -> u8 {
         let ig: u8 = (256.0
-            * (self.values[1] / (samples_per_pixel as f64))
+            * (self.values[1] / (samples_per_pixel as Float))
                 .sqrt()
                 .clamp(0.0, 0.999)) as u8;
         ig
@@ -45,13 +54,37 @@ impl Color {
 
     pub fn b(self, samples_per_pixel: u64) -> u8 {
         let ib: u8 = (256.0
-            * (self.values[2] / (samples_per_pixel as f64))
+            * (self.values[2] / (samples_per_pixel as Float))
                 .sqrt()
                 .clamp(0.0, 0.999)) as u8;
         ib
     }
 }
 
+impl Add for Color {
+    type Output = Color;
+
+    fn add(self, other: Self) -> Self::Output {
+        Color::new(
+            self.values[0] + other.values[0],
+            self.values[1] + other.values[1],
+            self.values[2] + other.values[2],
+        )
+    }
+}
+
+impl Sub for Color {
+    type Output = Color;
+
+    fn sub(self, other: Self) -> Self::Output {
+        Color::new(
+            self.values[0] - other.values[0],
+            self.values[1] - other.values[1],
+            self.values[2] - other.values[2],
+        )
+    }
+}
+
 impl AddAssign for Color {
     fn add_assign(&mut self, other: Self) {
         *self = Color {
@@ -64,10 +97,10 @@ impl AddAssign for Color {
     }
 }
 
-impl Mul<f64> for Color {
+impl Mul<Float> for Color {
     type Output = Color;
 
-    fn mul(self, other: f64) -> Self::Output {
+    fn mul(self, other: Float) -> Self::Output {
         Color::new(
             self.values[0] * other,
             self.values[1] * other,
@@ -86,3 +119,15 @@ impl Mul for Color {
         )
     }
 }
+
+impl Div<Float> for Color {
+    type Output = Color;
+
+    fn div(self, other: Float) -> Self::Output {
+        Color::new(
+            self.values[0] / other,
+            self.values[1] / other,
+            self.values[2] / other,
+        )
+    }
+}
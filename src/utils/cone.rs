@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use super::aabb::AABB;
+use super::float::Float;
+use super::frame::{azimuth, Frame};
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::object::Object;
+use super::ray::Ray;
+use super::roots::solve_quadratic;
+use super::scatter::Scatter;
+use super::vec3::Vec3;
+
+// cone with its base disk on base and the apex height along axis
+#[derive(Clone)]
+pub struct Cone {
+    frame: Frame,
+    radius: Float,
+    height: Float,
+    capped: bool,
+    mat: Arc<dyn Scatter>,
+    movement: Vec3,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, axis: Vec3, radius: Float, height: Float, m: Arc<dyn Scatter>, d: Vec3) -> Cone {
+        Cone {
+            frame: Frame::new(base, axis),
+            radius,
+            height,
+            capped: true,
+            mat: m,
+            movement: d,
+        }
+    }
+
+    // without the base disk
+    pub fn open(mut self) -> Cone {
+        self.capped = false;
+        self
+    }
+}
+
+impl Hit for Cone {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let o = self.frame.to_local(r.origin());
+        let d = self.frame.dir_to_local(r.direction());
+        let mut closest = t_max;
+        let mut best = None;
+
+        // x^2 + z^2 = (k (h - y))^2 with k the slope of the side
+        let k2 = (self.radius / self.height).powi(2);
+        let h = self.height - o.y();
+        let a = d.x().powi(2) + d.z().powi(2) - k2 * d.y().powi(2);
+        let b = 2.0 * (o.x() * d.x() + o.z() * d.z() + k2 * h * d.y());
+        let c = o.x().powi(2) + o.z().powi(2) - k2 * h * h;
+        if let Some((t0, t1)) = solve_quadratic(a, b, c) {
+            for t in [t0, t1] {
+                // one newton step on the side, the quadratic gives the
+                // roots with less accuracy than spawn() allows for
+                let p = o + d * t;
+                let h = self.height - p.y();
+                let f = p.x().powi(2) + p.z().powi(2) - k2 * h * h;
+                let df = 2.0 * (p.x() * d.x() + p.z() * d.z() + k2 * h * d.y());
+                let t = if df != 0.0 { t - f / df } else { t };
+                let p = o + d * t;
+                // the other nappe of the double cone lies above the apex
+                if t < t_min || t > closest || p.y() < 0.0 || p.y() > self.height {
+                    continue;
+                }
+                closest = t;
+                let normal = Vec3::new(p.x(), k2 * (self.height - p.y()), p.z()).unit_vector();
+                best = Some((t, normal, (azimuth(p), p.y() / self.height)));
+            }
+        }
+
+        if self.capped && d.y() != 0.0 {
+            let t = -o.y() / d.y();
+            let p = o + d * t;
+            let rho = (p.x().powi(2) + p.z().powi(2)).sqrt();
+            if t >= t_min && t <= closest && rho <= self.radius {
+                best = Some((t, Vec3::new(0.0, -1.0, 0.0), (azimuth(p), rho / self.radius)));
+            }
+        }
+
+        let (t, normal, uv) = best?;
+        Some(HitRecord::new(r, t, self.frame.dir_to_world(normal), self.mat.as_ref(), uv))
+    }
+}
+
+impl Object for Cone {
+    fn bounding_box(&self) -> AABB {
+        let r = self.radius;
+        self.frame.bounds(Vec3::new(-r, 0.0, -r), Vec3::new(r, self.height, r))
+    }
+
+    fn step_frame(&self, time_delta: Float) -> Box<dyn Object> {
+        let mut next = self.clone();
+        next.frame = self.frame.translated(self.movement * time_delta);
+        Box::new(next)
+    }
+
+    fn material(&self) -> Arc<dyn Scatter> {
+        self.mat.clone()
+    }
+
+    fn movement(&self) -> Vec3 {
+        self.movement
+    }
+}
@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use super::aabb::AABB;
+use super::float::Float;
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::object::Object;
+use super::ray::Ray;
+use super::scatter::Scatter;
+use super::vec3::Vec3;
+
+// crossings gathered per child before giving up on a degenerate surface
+const MAX_CROSSINGS: usize = 32;
+
+#[derive(Clone, Copy)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(self, a: bool, b: bool) -> bool {
+        match self {
+            CsgOp::Union => a || b,
+            CsgOp::Intersection => a && b,
+            CsgOp::Difference => a && !b,
+        }
+    }
+}
+
+// boolean combination of two closed objects, surfaces keep the material
+// of the child they come from
+pub struct Csg {
+    op: CsgOp,
+    a: Box<dyn Object>,
+    b: Box<dyn Object>,
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, a: Box<dyn Object>, b: Box<dyn Object>) -> Csg {
+        Csg { op, a, b }
+    }
+
+    pub fn union(a: Box<dyn Object>, b: Box<dyn Object>) -> Csg {
+        Csg::new(CsgOp::Union, a, b)
+    }
+
+    pub fn intersection(a: Box<dyn Object>, b: Box<dyn Object>) -> Csg {
+        Csg::new(CsgOp::Intersection, a, b)
+    }
+
+    pub fn difference(a: Box<dyn Object>, b: Box<dyn Object>) -> Csg {
+        Csg::new(CsgOp::Difference, a, b)
+    }
+}
+
+// every surface crossing along the whole line, front_face tells whether
+// the ray enters or leaves the object there
+fn crossings<'a>(object: &'a dyn Object, r: &Ray, t_max: Float) -> Vec<HitRecord<'a>> {
+    let mut out = Vec::new();
+    let mut t = Float::NEG_INFINITY;
+    while out.len() < MAX_CROSSINGS {
+        match object.hit(r, t, t_max) {
+            Some(rec) => {
+                t = rec.t + Float::EPSILON.sqrt() * rec.t.abs().max(1.0);
+                out.push(rec);
+            }
+            None => break,
+        }
+    }
+    out
+}
+
+impl Hit for Csg {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let a = crossings(self.a.as_ref(), r, t_max);
+        if a.is_empty() && !matches!(self.op, CsgOp::Union) {
+            return None;
+        }
+        let b = crossings(self.b.as_ref(), r, t_max);
+
+        // walk both interval lists in order and report the first crossing
+        // that changes whether the ray is inside the combined solid
+        let (mut inside_a, mut inside_b) = (false, false);
+        let mut inside = false;
+        let mut a = a.into_iter().peekable();
+        let mut b = b.into_iter().peekable();
+        loop {
+            let from_a = match (a.peek(), b.peek()) {
+                (Some(ra), Some(rb)) => ra.t <= rb.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+            let mut rec = if from_a {
+                let rec = a.next().unwrap();
+                inside_a = rec.front_face;
+                rec
+            } else {
+                let rec = b.next().unwrap();
+                inside_b = rec.front_face;
+                rec
+            };
+
+            let now_inside = self.op.inside(inside_a, inside_b);
+            if now_inside != inside && rec.t >= t_min {
+                // the normal already faces the ray, only the side changes
+                rec.front_face = now_inside;
+                return Some(rec);
+            }
+            inside = now_inside;
+        }
+    }
+}
+
+impl Object for Csg {
+    fn bounding_box(&self) -> AABB {
+        let a = self.a.bounding_box();
+        let b = self.b.bounding_box();
+        match self.op {
+            CsgOp::Union => AABB::union(&a, &b),
+            CsgOp::Intersection => {
+                let close = Vec3::max(a.close_corner, b.close_corner);
+                let far = Vec3::max(close, Vec3::min(a.far_corner, b.far_corner));
+                AABB::new(close, far)
+            }
+            CsgOp::Difference => a,
+        }
+    }
+
+    fn step_frame(&self, time_delta: Float) -> Box<dyn Object> {
+        Box::new(Csg::new(
+            self.op,
+            self.a.step_frame(time_delta),
+            self.b.step_frame(time_delta),
+        ))
+    }
+
+    fn material(&self) -> Arc<dyn Scatter> {
+        self.a.material()
+    }
+
+    fn movement(&self) -> Vec3 {
+        self.a.movement()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Csg;
+    use crate::utils::color::Color;
+    use crate::utils::dielectric::Dielectric;
+    use crate::utils::float::Float;
+    use crate::utils::hit::Hit;
+    use crate::utils::lambertian::Lambertian;
+    use crate::utils::medium::MediumStack;
+    use crate::utils::object::Object;
+    use crate::utils::ray::Ray;
+    use crate::utils::scatter::Scatter;
+    use crate::utils::sphere::Sphere;
+    use crate::utils::vec3::Vec3;
+
+    fn sphere(x: Float, radius: Float, mat: Arc<dyn Scatter>) -> Box<dyn Object> {
+        Box::new(Sphere::new(Vec3::new(x, 0.0, 0.0), radius, mat, Vec3::new(0.0, 0.0, 0.0)))
+    }
+
+    fn gray() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // every crossing along the ray as (t, entering)
+    fn walk(object: &dyn Object, r: &Ray) -> Vec<(Float, bool)> {
+        let mut out = Vec::new();
+        let mut t = 0.0;
+        while let Some(rec) = object.hit(r, t, Float::INFINITY) {
+            // the reported normal always faces the ray
+            assert!(rec.normal.dot(r.direction()) < 0.0);
+            out.push((rec.t, rec.front_face));
+            t = rec.t + 1e-4;
+        }
+        out
+    }
+
+    fn assert_crossings(found: Vec<(Float, bool)>, expected: &[(Float, bool)]) {
+        assert_eq!(found.len(), expected.len(), "{:?}", found);
+        for (&(t, front), &(et, efront)) in found.iter().zip(expected) {
+            assert!((t - et).abs() < 1e-4 && front == efront, "{:?}", found);
+        }
+    }
+
+    #[test]
+    fn boolean_crossings() {
+        let along = |x: Float| Ray::new(Vec3::new(x, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        // two overlapping spheres, the inner surfaces disappear
+        let union = Csg::union(sphere(-0.5, 1.0, gray()), sphere(0.5, 1.0, gray()));
+        assert_crossings(walk(&union, &along(-5.0)), &[(3.5, true), (6.5, false)]);
+        assert_crossings(walk(&union, &along(0.0)), &[(1.5, false)]);
+
+        // the lens where both overlap
+        let lens = Csg::intersection(sphere(-0.5, 1.0, gray()), sphere(0.5, 1.0, gray()));
+        assert_crossings(walk(&lens, &along(-5.0)), &[(4.5, true), (5.5, false)]);
+        assert_crossings(walk(&lens, &along(0.0)), &[(0.5, false)]);
+        // through the first sphere only
+        assert!(lens.hit(&Ray::new(Vec3::new(-1.2, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), 0.0, Float::INFINITY).is_none());
+
+        // a hollow shell, entered and left twice
+        let shell = Csg::difference(sphere(0.0, 2.0, gray()), sphere(0.0, 1.0, gray()));
+        assert_crossings(walk(&shell, &along(-5.0)), &[(3.0, true), (4.0, false), (6.0, true), (7.0, false)]);
+        assert_crossings(walk(&shell, &along(-1.5)), &[(0.5, false), (2.5, true), (3.5, false)]);
+        // starting in the hollow
+        assert_crossings(walk(&shell, &along(0.0)), &[(1.0, true), (2.0, false)]);
+    }
+
+    #[test]
+    fn bounds_of_intersection_and_difference() {
+        let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-9;
+        let lens = Csg::intersection(sphere(-0.5, 1.0, gray()), sphere(0.5, 1.0, gray()));
+        let aabb = lens.bounding_box();
+        assert!(close(aabb.close_corner, Vec3::new(-0.5, -1.0, -1.0)) && close(aabb.far_corner, Vec3::new(0.5, 1.0, 1.0)));
+
+        // disjoint children leave an empty box, not an inverted one
+        let empty = Csg::intersection(sphere(-3.0, 1.0, gray()), sphere(3.0, 1.0, gray())).bounding_box();
+        for i in 0..3 {
+            assert!(empty.close_corner.values[i] <= empty.far_corner.values[i]);
+        }
+        assert_eq!(empty.far_corner.x(), empty.close_corner.x());
+
+        // a difference never grows past what it cuts from
+        let bite = Csg::difference(sphere(0.0, 1.0, gray()), sphere(1.0, 1.0, gray()));
+        let aabb = bite.bounding_box();
+        assert!(close(aabb.close_corner, Vec3::new(-1.0, -1.0, -1.0)) && close(aabb.far_corner, Vec3::new(1.0, 1.0, 1.0)));
+    }
+
+    // the inner surface of a hollow glass ball has to take the path back
+    // out of the glass, not into a second layer of it
+    #[test]
+    fn hollow_glass_through_the_medium_stack() {
+        let glass = || -> Arc<dyn Scatter> { Arc::new(Dielectric::new(1.5)) };
+        let shell = Csg::difference(sphere(0.0, 2.0, glass()), sphere(0.0, 1.0, glass()));
+        let mut media = MediumStack::default();
+        let mut ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let mut inside = Vec::new();
+        while let Some(rec) = shell.hit(&ray, 0.0, Float::INFINITY) {
+            let medium = rec.mat.medium().unwrap();
+            // head on, the ray is sometimes reflected instead, which leaves
+            // the stack as it was
+            let next = loop {
+                let next = media.cross(&ray, &rec, medium, 550.0);
+                if next.direction().x() > 0.0 {
+                    break next;
+                }
+            };
+            assert!(next.origin().x() > rec.p.x());
+            inside.push(media.current().map(|m| m.ior.at(550.0)));
+            ray = next;
+        }
+        assert_eq!(inside, [Some(1.5), None, Some(1.5), None]);
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use super::aabb::AABB;
+use super::float::Float;
+use super::frame::{azimuth, Frame};
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::object::Object;
+use super::ray::Ray;
+use super::roots::solve_quadratic;
+use super::scatter::Scatter;
+use super::vec3::Vec3;
+
+// cylinder standing on base and extending height along axis
+#[derive(Clone)]
+pub struct Cylinder {
+    frame: Frame,
+    radius: Float,
+    height: Float,
+    capped: bool,
+    mat: Arc<dyn Scatter>,
+    movement: Vec3,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, axis: Vec3, radius: Float, height: Float, m: Arc<dyn Scatter>, d: Vec3) -> Cylinder {
+        Cylinder {
+            frame: Frame::new(base, axis),
+            radius,
+            height,
+            capped: true,
+            mat: m,
+            movement: d,
+        }
+    }
+
+    // just the side wall, a tube
+    pub fn open(mut self) -> Cylinder {
+        self.capped = false;
+        self
+    }
+}
+
+impl Hit for Cylinder {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let o = self.frame.to_local(r.origin());
+        let d = self.frame.dir_to_local(r.direction());
+        let mut closest = t_max;
+        let mut best = None;
+
+        let a = d.x().powi(2) + d.z().powi(2);
+        let b = 2.0 * (o.x() * d.x() + o.z() * d.z());
+        let c = o.x().powi(2) + o.z().powi(2) - self.radius.powi(2);
+        if a > 0.0 {
+            if let Some((t0, t1)) = solve_quadratic(a, b, c) {
+                for t in [t0, t1] {
+                    let p = o + d * t;
+                    if t < t_min || t > closest || p.y() < 0.0 || p.y() > self.height {
+                        continue;
+                    }
+                    closest = t;
+                    let normal = Vec3::new(p.x(), 0.0, p.z()) / self.radius;
+                    best = Some((t, normal, (azimuth(p), p.y() / self.height)));
+                }
+            }
+        }
+
+        if self.capped && d.y() != 0.0 {
+            for (y, normal) in [(0.0, -1.0), (self.height, 1.0)] {
+                let t = (y - o.y()) / d.y();
+                let p = o + d * t;
+                let rho = (p.x().powi(2) + p.z().powi(2)).sqrt();
+                if t < t_min || t > closest || rho > self.radius {
+                    continue;
+                }
+                closest = t;
+                best = Some((t, Vec3::new(0.0, normal, 0.0), (azimuth(p), rho / self.radius)));
+            }
+        }
+
+        let (t, normal, uv) = best?;
+        Some(HitRecord::new(r, t, self.frame.dir_to_world(normal), self.mat.as_ref(), uv))
+    }
+}
+
+impl Object for Cylinder {
+    fn bounding_box(&self) -> AABB {
+        let r = self.radius;
+        self.frame.bounds(Vec3::new(-r, 0.0, -r), Vec3::new(r, self.height, r))
+    }
+
+    fn step_frame(&self, time_delta: Float) -> Box<dyn Object> {
+        let mut next = self.clone();
+        next.frame = self.frame.translated(self.movement * time_delta);
+        Box::new(next)
+    }
+
+    fn material(&self) -> Arc<dyn Scatter> {
+        self.mat.clone()
+    }
+
+    fn movement(&self) -> Vec3 {
+        self.movement
+    }
+}
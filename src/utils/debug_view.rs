@@ -0,0 +1,98 @@
+use super::aabb::{TraversalCounts, AABB};
+use super::color::Color;
+use super::film::Film;
+use super::float::Float;
+use super::vec3::Vec3;
+
+// what the heatmap render mode counts per pixel
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Heatmap {
+    BoxTests,
+    PrimitiveTests,
+}
+
+impl Heatmap {
+    pub fn from_name(name: &str) -> Option<Heatmap> {
+        match name {
+            "boxes" => Some(Heatmap::BoxTests),
+            "primitives" => Some(Heatmap::PrimitiveTests),
+            _ => None,
+        }
+    }
+
+    pub fn count(self, counts: &TraversalCounts) -> usize {
+        match self {
+            Heatmap::BoxTests => counts.box_tests,
+            Heatmap::PrimitiveTests => counts.primitive_tests,
+        }
+    }
+}
+
+// false color from black through blue, green and yellow to red, t in [0, 1]
+pub fn heat_color(t: Float) -> Color {
+    const STOPS: [(Float, Float, Float); 5] = [
+        (0.0, 0.0, 0.0),
+        (0.0, 0.1, 0.9),
+        (0.0, 0.8, 0.2),
+        (1.0, 0.9, 0.0),
+        (1.0, 0.0, 0.0),
+    ];
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as Float;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as Float;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    Color::new(a.0 + (b.0 - a.0) * f, a.1 + (b.1 - a.1) * f, a.2 + (b.2 - a.2) * f)
+}
+
+// maps counts to colors with the largest count in the image at the top of
+// the scale, counts are stored top row first
+pub fn heatmap_film(width: usize, height: usize, counts: &[usize]) -> Film {
+    let most = counts.iter().cloned().max().unwrap_or(0).max(1);
+    let pixels = counts.iter().map(|&c| heat_color(c as Float / most as Float)).collect();
+    Film::new(width, height, pixels)
+}
+
+fn plot(film: &mut Film, x: Float, y: Float, color: Color) {
+    if x < 0.0 || y < 0.0 {
+        return;
+    }
+    let (x, y) = (x.round() as usize, y.round() as usize);
+    if x < film.width && y < film.height {
+        film.pixels[y * film.width + x] = color;
+    }
+}
+
+// draws the twelve edges of a box. edges are stepped in world space and
+// every point projected on its own, so parts behind the camera or outside
+// the projection simply drop out
+pub fn draw_box(film: &mut Film, aabb: &AABB, project: &dyn Fn(Vec3) -> Option<(Float, Float)>, color: Color) {
+    let (lo, hi) = (aabb.close_corner, aabb.far_corner);
+    let corner = |i: usize| {
+        Vec3::new(
+            if i & 1 == 0 { lo.x() } else { hi.x() },
+            if i & 2 == 0 { lo.y() } else { hi.y() },
+            if i & 4 == 0 { lo.z() } else { hi.z() },
+        )
+    };
+
+    for a in 0..8 {
+        for bit in [1, 2, 4] {
+            if a & bit != 0 {
+                continue;
+            }
+            let (p, q) = (corner(a), corner(a | bit));
+            // about two points per pixel, or a fixed amount when an end
+            // does not project
+            let steps = match (project(p), project(q)) {
+                (Some((x0, y0)), Some((x1, y1))) => ((x1 - x0).hypot(y1 - y0) * 2.0).clamp(1.0, 8192.0) as usize,
+                _ => 1024,
+            };
+            for s in 0..=steps {
+                let t = s as Float / steps as Float;
+                if let Some((x, y)) = project(p + (q - p) * t) {
+                    plot(film, x, y, color);
+                }
+            }
+        }
+    }
+}
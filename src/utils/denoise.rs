@@ -0,0 +1,214 @@
+use rayon::prelude::*;
+
+use super::aov::Aovs;
+use super::color::Color;
+use super::film::Film;
+use super::float::Float;
+
+// edge avoiding a-trous wavelet filter (dammertz et al. 2010)
+// the aovs act as edge stopping functions so the blur stays inside surfaces
+pub struct Denoiser {
+    pub iterations: u32,
+    pub sigma_color: Float,
+    pub sigma_normal: Float,
+    pub sigma_albedo: Float,
+    pub sigma_depth: Float,
+}
+
+// b3 spline taps, applied separably as a 5x5 kernel
+const KERNEL: [Float; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+impl Denoiser {
+    pub fn default() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 0.5,
+            sigma_normal: 0.1,
+            sigma_albedo: 0.1,
+            sigma_depth: 0.05,
+        }
+    }
+
+    pub fn denoise(&self, film: &Film, aovs: &Aovs) -> Film {
+        assert_eq!(film.width, aovs.width);
+        assert_eq!(film.height, aovs.height);
+
+        // filter the untextured irradiance so albedo detail survives. the
+        // sky has no albedo to divide out and is filtered as it is
+        let albedo: Vec<Color> = aovs.albedo
+            .iter()
+            .zip(aovs.object_id.iter())
+            .map(|(&a, id)| if id.is_some() { a } else { Color::new(1.0, 1.0, 1.0) })
+            .collect();
+        let mut irradiance: Vec<Color> = film.pixels
+            .iter()
+            .zip(albedo.iter())
+            .map(|(&c, &a)| demodulate(c, a))
+            .collect();
+
+        let mut sigma_color = self.sigma_color;
+        for i in 0..self.iterations {
+            irradiance = self.pass(&irradiance, aovs, 1 << i, sigma_color);
+            // finer detail is gone after each pass so tighten the color stop
+            sigma_color /= 2.0;
+        }
+
+        let pixels = irradiance
+            .iter()
+            .zip(albedo.iter())
+            .map(|(&c, &a)| remodulate(c, a))
+            .collect();
+        Film::new(film.width, film.height, pixels)
+    }
+
+    fn pass(&self, input: &[Color], aovs: &Aovs, step: usize, sigma_color: Float) -> Vec<Color> {
+        let width = aovs.width as i64;
+        let height = aovs.height as i64;
+        let step = step as i64;
+
+        (0..input.len()).into_par_iter().map(|idx| {
+            let x = idx as i64 % width;
+            let y = idx as i64 / width;
+
+            let color = input[idx];
+            let normal = aovs.normal[idx];
+            let albedo = aovs.albedo[idx];
+            let depth = aovs.depth[idx];
+
+            let mut sum = Color::new(0.0, 0.0, 0.0);
+            let mut total_weight = 0.0;
+
+            for (ky, wy) in KERNEL.iter().enumerate() {
+                for (kx, wx) in KERNEL.iter().enumerate() {
+                    let qx = x + (kx as i64 - 2) * step;
+                    let qy = y + (ky as i64 - 2) * step;
+                    if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                        continue;
+                    }
+                    let q = (qy * width + qx) as usize;
+
+                    let color_dist = (input[q] - color).length_squared();
+                    let normal_dist = (aovs.normal[q] - normal).length_squared();
+                    let albedo_dist = (aovs.albedo[q] - albedo).length_squared();
+                    let depth_dist = relative_depth(depth, aovs.depth[q]);
+
+                    let w = (-color_dist / sigma_color.powi(2)).exp()
+                        * (-normal_dist / self.sigma_normal.powi(2)).exp()
+                        * (-albedo_dist / self.sigma_albedo.powi(2)).exp()
+                        * (-depth_dist / self.sigma_depth.powi(2)).exp();
+                    let w = w * wx * wy;
+
+                    sum += input[q] * w;
+                    total_weight += w;
+                }
+            }
+
+            // the center tap always has weight, so this never divides by zero
+            sum / total_weight
+        }).collect()
+    }
+}
+
+fn relative_depth(a: Float, b: Float) -> Float {
+    if a.is_infinite() || b.is_infinite() {
+        return if a == b { 0.0 } else { Float::INFINITY };
+    }
+    ((a - b) / a.max(b).max(1e-4)).powi(2)
+}
+
+const MIN_ALBEDO: Float = 1e-3;
+
+fn demodulate(c: Color, albedo: Color) -> Color {
+    let c = c.values();
+    let a = albedo.values();
+    Color::new(c[0] / a[0].max(MIN_ALBEDO),
+               c[1] / a[1].max(MIN_ALBEDO),
+               c[2] / a[2].max(MIN_ALBEDO))
+}
+
+fn remodulate(c: Color, albedo: Color) -> Color {
+    let c = c.values();
+    let a = albedo.values();
+    Color::new(c[0] * a[0].max(MIN_ALBEDO),
+               c[1] * a[1].max(MIN_ALBEDO),
+               c[2] * a[2].max(MIN_ALBEDO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Denoiser;
+    use crate::utils::aov::{AovSample, Aovs};
+    use crate::utils::color::Color;
+    use crate::utils::film::Film;
+    use crate::utils::float::Float;
+    use crate::utils::vec3::Vec3;
+
+    const SIZE: usize = 16;
+
+    fn surface(normal: Vec3) -> AovSample {
+        AovSample {
+            depth: 5.0,
+            normal,
+            albedo: Color::new(0.5, 0.5, 0.5),
+            object_id: Some(0),
+            material_id: Some(0),
+            motion: (0.0, 0.0),
+        }
+    }
+
+    fn denoise(pixel: impl Fn(usize, usize) -> (Float, AovSample)) -> Film {
+        let (colors, samples): (Vec<_>, Vec<_>) = (0..SIZE * SIZE)
+            .map(|idx| {
+                let (c, sample) = pixel(idx % SIZE, idx / SIZE);
+                (Color::new(c, c, c), sample)
+            })
+            .unzip();
+        let film = Film::new(SIZE, SIZE, colors);
+        Denoiser::default().denoise(&film, &Aovs::from_samples(SIZE, SIZE, samples))
+    }
+
+    fn gray(film: &Film, x: usize, y: usize) -> Float {
+        film.get(x, y).values()[0]
+    }
+
+    #[test]
+    fn flat_image_stays_flat() {
+        let film = denoise(|_, _| (0.3, surface(Vec3::new(0.0, 1.0, 0.0))));
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                assert!((gray(&film, x, y) - 0.3).abs() < 1e-5);
+            }
+        }
+    }
+
+    // sky pixels have zero albedo, dividing by the clamped albedo would
+    // blow their noise up past the color stop and keep it all
+    #[test]
+    fn sky_noise_is_smoothed() {
+        let film = denoise(|x, y| {
+            let c = if (x + y) % 2 == 0 { 0.6 } else { 0.8 };
+            if x < SIZE / 2 { (c, AovSample::background()) } else { (0.3, surface(Vec3::new(0.0, 1.0, 0.0))) }
+        });
+        for y in 2..SIZE - 2 {
+            for x in 2..SIZE / 2 - 2 {
+                assert!((gray(&film, x, y) - 0.7).abs() < 0.05, "{} {} {}", x, y, gray(&film, x, y));
+            }
+        }
+    }
+
+    // two walls meeting at a crease keep their own brightness
+    #[test]
+    fn normals_stop_the_blur() {
+        let film = denoise(|x, _| {
+            if x < SIZE / 2 {
+                (0.2, surface(Vec3::new(1.0, 0.0, 0.0)))
+            } else {
+                (0.8, surface(Vec3::new(0.0, 1.0, 0.0)))
+            }
+        });
+        for y in 0..SIZE {
+            assert!((gray(&film, SIZE / 2 - 1, y) - 0.2).abs() < 0.01);
+            assert!((gray(&film, SIZE / 2, y) - 0.8).abs() < 0.01);
+        }
+    }
+}
@@ -1,52 +1,141 @@
 use super::{color::Color, ray::Ray, scatter::Scatter};
+use super::hit_record::HitRecord;
+use super::medium::Medium;
+use super::spectrum::LAMBDA_D;
+use super::float::Float;
 use rand::Rng;
 
-pub struct Dielectric {
-    ir: f64,
+// index of refraction as a function of wavelength
+#[derive(Clone, Copy)]
+pub enum Ior {
+    Constant(Float),
+    // n = a + b / lambda^2 with lambda in micrometers
+    Cauchy { a: Float, b: Float },
+    // n^2 = 1 + sum b_i lambda^2 / (lambda^2 - c_i), lambda in micrometers
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
 }
 
-impl Dielectric {
-    pub fn new(index_of_refraction: f64) -> Dielectric {
-        Dielectric {
-            ir: (index_of_refraction),
+impl Ior {
+    // schott bk7 crown glass
+    #[cfg_attr(feature = "f32", allow(clippy::excessive_precision))]
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    pub fn at(&self, lambda_nm: Float) -> Float {
+        let l = lambda_nm / 1000.0;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / (l * l),
+            Ior::Sellmeier { b, c } => {
+                let l2 = l * l;
+                let sum: Float = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
         }
     }
+}
+
+fn reflectance(cosine: Float, ref_idx: Float) -> Float {
+    // Shlick's approximation
+    let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+// picks reflection or refraction at an interface with the given ratio of
+// indices, the flag tells whether the ray went through
+pub fn refract_or_reflect(r_in: &Ray, rec: &HitRecord, refraction_ratio: Float) -> (Ray, bool) {
+    let unit_direction = r_in.direction().unit_vector();
+
+    let cos_theta = (unit_direction * (-1.0)).dot(rec.normal).min(1.0);
+    let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+
+    let mut rng = rand::thread_rng();
+    let cannot_refract = refraction_ratio * sin_theta > 1.0;
+    let will_reflect = rng.gen::<Float>() < reflectance(cos_theta, refraction_ratio);
 
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-        // Shlick's approximation
-        let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
-        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    if cannot_refract || will_reflect {
+        (rec.spawn(unit_direction.reflect(rec.normal)), false)
+    } else {
+        (rec.spawn(unit_direction.refract(rec.normal, refraction_ratio)), true)
     }
 }
 
-impl Scatter for Dielectric {
-    fn scatter(
-        &self,
-        r_in: &super::ray::Ray,
-        rec: &super::hit_record::HitRecord,
-    ) -> Option<(super::color::Color, super::ray::Ray)> {
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
+pub struct Dielectric {
+    ior: Ior,
+    absorption: Color,
+    priority: u32,
+}
 
-        let unit_direction = r_in.direction().unit_vector();
+impl Dielectric {
+    pub fn new(index_of_refraction: Float) -> Dielectric {
+        Dielectric::dispersive(Ior::Constant(index_of_refraction))
+    }
 
-        let cos_theta = (unit_direction * (-1.0)).dot(rec.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+    pub fn dispersive(ior: Ior) -> Dielectric {
+        Dielectric {
+            ior,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            priority: 0,
+        }
+    }
+
+    // absorption coefficient per unit length inside the glass
+    pub fn with_absorption(mut self, absorption: Color) -> Dielectric {
+        self.absorption = absorption;
+        self
+    }
 
-        let mut rng = rand::thread_rng();
-        let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let will_reflect = rng.gen::<f64>() < Self::reflectance(cos_theta, refraction_ratio);
+    // tints the glass so that a path of the given length keeps the color
+    pub fn with_tint(self, color: Color, distance: Float) -> Dielectric {
+        let [r, g, b] = color.values();
+        let sigma = |c: Float| -c.max(1e-6).ln() / distance;
+        self.with_absorption(Color::new(sigma(r), sigma(g), sigma(b)))
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Dielectric {
+        self.priority = priority;
+        self
+    }
 
-        let direction = if cannot_refract || will_reflect {
-            unit_direction.reflect(rec.normal)
+    fn refract_with(&self, ir: Float, r_in: &Ray, rec: &HitRecord) -> Ray {
+        let refraction_ratio = if rec.front_face {
+            1.0 / ir
         } else {
-            unit_direction.refract(rec.normal, refraction_ratio)
+            ir
         };
-        let scattered = Ray::new(rec.p, direction);
+        refract_or_reflect(r_in, rec, refraction_ratio).0
+    }
+}
 
+// the integrator crosses dielectrics through medium() and applies the
+// absorption itself, scattering directly treats the surface on its own
+impl Scatter for Dielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+    ) -> Option<(Color, Ray)> {
+        let scattered = self.refract_with(self.ior.at(LAMBDA_D), r_in, rec);
         Some((Color::new(1.0, 1.0, 1.0), scattered))
     }
+
+    fn scatter_spectral(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        lambda: Float,
+    ) -> Option<(Float, Ray)> {
+        let scattered = self.refract_with(self.ior.at(lambda), r_in, rec);
+        Some((1.0, scattered))
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        Some(Medium {
+            ior: self.ior,
+            absorption: self.absorption,
+            priority: self.priority,
+        })
+    }
 }
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use super::aabb::AABB;
+use super::float::Float;
+use super::frame::{azimuth, Frame};
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::object::Object;
+use super::ray::Ray;
+use super::scatter::Scatter;
+use super::vec3::Vec3;
+
+// flat disk facing along normal, with a hole when inner is above zero
+#[derive(Clone)]
+pub struct Disk {
+    frame: Frame,
+    inner: Float,
+    radius: Float,
+    mat: Arc<dyn Scatter>,
+    movement: Vec3,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: Float, m: Arc<dyn Scatter>, d: Vec3) -> Disk {
+        Disk::annulus(center, normal, 0.0, radius, m, d)
+    }
+
+    pub fn annulus(center: Vec3, normal: Vec3, inner: Float, radius: Float, m: Arc<dyn Scatter>, d: Vec3) -> Disk {
+        Disk {
+            frame: Frame::new(center, normal),
+            inner,
+            radius,
+            mat: m,
+            movement: d,
+        }
+    }
+}
+
+impl Hit for Disk {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let o = self.frame.to_local(r.origin());
+        let d = self.frame.dir_to_local(r.direction());
+        if d.y() == 0.0 {
+            return None;
+        }
+
+        let t = -o.y() / d.y();
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let p = o + d * t;
+        let rho = (p.x().powi(2) + p.z().powi(2)).sqrt();
+        if rho < self.inner || rho > self.radius {
+            return None;
+        }
+
+        let v = (rho - self.inner) / (self.radius - self.inner);
+        Some(HitRecord::new(r, t, self.frame.y, self.mat.as_ref(), (azimuth(p), v)))
+    }
+}
+
+impl Object for Disk {
+    fn bounding_box(&self) -> AABB {
+        // a little thickness so the box never collapses to a plane
+        let r = self.radius;
+        self.frame.bounds(Vec3::new(-r, -1e-4, -r), Vec3::new(r, 1e-4, r))
+    }
+
+    fn step_frame(&self, time_delta: Float) -> Box<dyn Object> {
+        let mut next = self.clone();
+        next.frame = self.frame.translated(self.movement * time_delta);
+        Box::new(next)
+    }
+
+    fn material(&self) -> Arc<dyn Scatter> {
+        self.mat.clone()
+    }
+
+    fn movement(&self) -> Vec3 {
+        self.movement
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::film::Film;
+
+// channel pixel type for 32 bit floats
+const FLOAT: i32 = 2;
+
+// writes the linear film as an uncompressed single part scanline exr
+// with float R, G and B channels
+#[cfg_attr(feature = "f32", allow(clippy::unnecessary_cast))]
+pub fn write_exr(path: &Path, film: &Film) -> io::Result<()> {
+    let mut header = Vec::new();
+
+    // channels have to be listed in alphabetical order
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&FLOAT.to_le_bytes());
+        // pLinear and three reserved bytes, then x and y sampling
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels);
+
+    attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for v in [0, 0, film.width as i32 - 1, film.height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    // increasing y, top row first like the film
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    // magic number and version 2 without any flags
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0])?;
+    out.write_all(&header)?;
+
+    // uncompressed files hold one scanline per block, the offset table
+    // points at each block from the start of the file
+    let line_size = 8 + film.width * 3 * 4;
+    let table_start = 8 + header.len();
+    let first_line = table_start + film.height * 8;
+    for y in 0..film.height {
+        out.write_all(&((first_line + y * line_size) as u64).to_le_bytes())?;
+    }
+
+    for y in 0..film.height {
+        let row = &film.pixels[y * film.width..(y + 1) * film.width];
+        out.write_all(&(y as i32).to_le_bytes())?;
+        out.write_all(&((film.width * 3 * 4) as i32).to_le_bytes())?;
+        for channel in [2, 1, 0] {
+            for color in row {
+                out.write_all(&(color.values()[channel] as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_exr;
+    use crate::utils::color::Color;
+    use crate::utils::film::Film;
+    use crate::utils::float::Float;
+
+    fn read_i32(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    // every offset has to land on the scanline block it names, and the
+    // blocks have to fill the rest of the file
+    #[test]
+    fn line_offsets_point_at_their_scanlines() {
+        let (width, height) = (3, 4);
+        let pixels = (0..width * height).map(|i| Color::new(i as Float, 0.5, -1.0)).collect();
+        let film = Film::new(width, height, pixels);
+        let path = std::env::temp_dir().join(format!("exr-test-{}.exr", std::process::id()));
+        write_exr(&path, &film).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes[..8], [0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+        // skip the attributes, a name, a type, a size and the value each
+        let mut pos = 8;
+        while bytes[pos] != 0 {
+            for _ in 0..2 {
+                pos += bytes[pos..].iter().position(|&b| b == 0).unwrap() + 1;
+            }
+            pos += 4 + read_i32(&bytes, pos) as usize;
+        }
+        pos += 1;
+
+        let line_size = 8 + width * 3 * 4;
+        let first_line = pos + height * 8;
+        assert_eq!(bytes.len(), first_line + height * line_size);
+        for y in 0..height {
+            let offset = u64::from_le_bytes(bytes[pos + 8 * y..pos + 8 * y + 8].try_into().unwrap()) as usize;
+            assert_eq!(offset, first_line + y * line_size);
+            assert_eq!(read_i32(&bytes, offset), y as i32);
+            assert_eq!(read_i32(&bytes, offset + 4), (width * 3 * 4) as i32);
+            // channels are stored b, g, r, each a full row
+            let red = offset + 8 + 2 * width * 4;
+            for x in 0..width {
+                let value = f32::from_le_bytes(bytes[red + 4 * x..red + 4 * x + 4].try_into().unwrap());
+                assert_eq!(value, (y * width + x) as f32);
+            }
+            assert_eq!(f32::from_le_bytes(bytes[offset + 8..offset + 12].try_into().unwrap()), -1.0);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use super::color::Color;
+use super::filter::Filter;
+use super::float::Float;
+
+// averaged linear radiance per pixel, top row first
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Film {
+        assert_eq!(pixels.len(), width * height);
+        Film { width, height, pixels }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    // gamma corrected 8 bit rgb, the layout gif::Frame::from_rgb expects
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| [color.r(1), color.g(1), color.b(1)])
+            .collect()
+    }
+}
+
+// weighted sums of filtered samples, resolved into a Film once rendering is done
+pub struct FilmAccumulator {
+    width: usize,
+    height: usize,
+    sum: Vec<Color>,
+    weight: Vec<Float>,
+}
+
+impl FilmAccumulator {
+    pub fn new(width: usize, height: usize) -> FilmAccumulator {
+        FilmAccumulator {
+            width,
+            height,
+            sum: vec![Color::new(0.0, 0.0, 0.0); width * height],
+            weight: vec![0.0; width * height],
+        }
+    }
+
+    // x and y are continuous raster coordinates with pixel centers at +0.5,
+    // the sample is added to every pixel the filter footprint covers
+    pub fn splat(&mut self, x: Float, y: Float, color: Color, filter: &Filter) {
+        let x0 = (x - 0.5 - filter.radius).ceil().max(0.0) as usize;
+        let y0 = (y - 0.5 - filter.radius).ceil().max(0.0) as usize;
+        let x1 = ((x - 0.5 + filter.radius).floor() as i64).min(self.width as i64 - 1);
+        let y1 = ((y - 0.5 + filter.radius).floor() as i64).min(self.height as i64 - 1);
+        if x1 < 0 || y1 < 0 {
+            return;
+        }
+
+        for py in y0..=(y1 as usize) {
+            for px in x0..=(x1 as usize) {
+                let w = filter.eval(px as Float + 0.5 - x, py as Float + 0.5 - y);
+                if w == 0.0 {
+                    continue;
+                }
+                let idx = py * self.width + px;
+                self.sum[idx] += color * w;
+                self.weight[idx] += w;
+            }
+        }
+    }
+
+    pub fn merge(mut self, other: FilmAccumulator) -> FilmAccumulator {
+        for (idx, (color, w)) in other.sum.into_iter().zip(other.weight).enumerate() {
+            self.sum[idx] += color;
+            self.weight[idx] += w;
+        }
+        self
+    }
+
+    pub fn resolve(self) -> Film {
+        let pixels = self.sum
+            .into_iter()
+            .zip(self.weight)
+            .map(|(color, w)| {
+                // negative lobes can cancel out, leave those pixels black
+                if w > 1e-8 { color / w } else { Color::new(0.0, 0.0, 0.0) }
+            })
+            .collect();
+        Film::new(self.width, self.height, pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FilmAccumulator;
+    use crate::utils::color::Color;
+    use crate::utils::filter::{Filter, FilterKind};
+    use crate::utils::float::Float;
+
+    // splatting divides by the summed weights, so any filter has to give a
+    // constant image back, borders included
+    #[test]
+    fn constant_image_stays_constant() {
+        for kind in [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos] {
+            let filter = Filter::new(kind, 1.5);
+            let mut film = FilmAccumulator::new(6, 4);
+            for sy in 0..16 {
+                for sx in 0..24 {
+                    let (x, y) = ((sx as Float + 0.5) / 4.0, (sy as Float + 0.5) / 4.0);
+                    film.splat(x, y, Color::new(0.25, 0.5, 0.75), &filter);
+                }
+            }
+            for pixel in film.resolve().pixels {
+                let [r, g, b] = pixel.values();
+                assert!((r - 0.25).abs() < 1e-5 && (g - 0.5).abs() < 1e-5 && (b - 0.75).abs() < 1e-5, "{:?}", kind);
+            }
+        }
+    }
+
+    // a half pixel box keeps each sample in the pixel it falls in
+    #[test]
+    fn box_splats_into_one_pixel() {
+        let mut film = FilmAccumulator::new(3, 3);
+        film.splat(1.2, 1.7, Color::new(1.0, 1.0, 1.0), &Filter::default());
+        // samples outside the image are dropped without touching it
+        film.splat(-3.0, 10.0, Color::new(1.0, 1.0, 1.0), &Filter::new(FilterKind::Gaussian, 2.0));
+        let film = film.resolve();
+        for y in 0..3 {
+            for x in 0..3 {
+                let expected = if (x, y) == (1, 1) { 1.0 } else { 0.0 };
+                assert_eq!(film.get(x, y).values()[0], expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use super::float::{consts::PI, Float};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+// separable pixel reconstruction filter, radius is in pixels
+#[derive(Clone, Copy, Debug)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: Float,
+}
+
+// falloff of the gaussian, matches pbrt's default
+const GAUSSIAN_ALPHA: Float = 2.0;
+// mitchell-netravali parameters recommended in the paper
+const MITCHELL_B: Float = 1.0 / 3.0;
+const MITCHELL_C: Float = 1.0 / 3.0;
+// number of sinc lobes inside the lanczos window
+const LANCZOS_TAU: Float = 3.0;
+
+impl Filter {
+    pub fn new(kind: FilterKind, radius: Float) -> Filter {
+        assert!(radius > 0.0);
+        Filter { kind, radius }
+    }
+
+    // a half pixel box keeps every sample inside its own pixel
+    pub fn default() -> Filter {
+        Filter::new(FilterKind::Box, 0.5)
+    }
+
+    pub fn from_name(name: &str) -> Option<FilterKind> {
+        match name {
+            "box" => Some(FilterKind::Box),
+            "tent" => Some(FilterKind::Tent),
+            "gaussian" => Some(FilterKind::Gaussian),
+            "mitchell" => Some(FilterKind::Mitchell),
+            "lanczos" => Some(FilterKind::Lanczos),
+            _ => None,
+        }
+    }
+
+    pub fn eval(&self, dx: Float, dy: Float) -> Float {
+        self.eval_1d(dx) * self.eval_1d(dy)
+    }
+
+    fn eval_1d(&self, x: Float) -> Float {
+        let x = x.abs();
+        if x > self.radius {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => self.radius - x,
+            FilterKind::Gaussian => {
+                let g = |d: Float| (-GAUSSIAN_ALPHA * d * d).exp();
+                (g(x) - g(self.radius)).max(0.0)
+            }
+            // the cubic is defined on [-2, 2]
+            FilterKind::Mitchell => mitchell(2.0 * x / self.radius),
+            FilterKind::Lanczos => {
+                let x = x / self.radius * LANCZOS_TAU;
+                sinc(x) * sinc(x / LANCZOS_TAU)
+            }
+        }
+    }
+}
+
+fn mitchell(x: Float) -> Float {
+    let (b, c) = (MITCHELL_B, MITCHELL_C);
+    let x = x.abs();
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+            + (6.0 - 2.0 * b)
+    } else if x < 2.0 {
+        (-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x.powi(2)
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    } else {
+        0.0
+    };
+    value / 6.0
+}
+
+fn sinc(x: Float) -> Float {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, FilterKind};
+    use crate::utils::float::Float;
+
+    const KINDS: [FilterKind; 5] = [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos];
+
+    // every kernel peaks at the center, is symmetric and ends at the radius
+    #[test]
+    fn kernel_shape() {
+        for kind in KINDS {
+            let filter = Filter::new(kind, 2.0);
+            let center = filter.eval(0.0, 0.0);
+            assert!(center > 0.0, "{:?}", kind);
+            for i in 1..40 {
+                let x = i as Float * 0.05;
+                assert!(filter.eval(x, 0.0) <= center, "{:?} at {}", kind, x);
+                assert_eq!(filter.eval(x, 0.3), filter.eval(-x, -0.3), "{:?} at {}", kind, x);
+            }
+            assert_eq!(filter.eval(2.01, 0.0), 0.0, "{:?}", kind);
+            assert_eq!(filter.eval(0.0, -2.01), 0.0, "{:?}", kind);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+// precision used for all geometry and shading. building with the f32
+// feature halves the memory traffic at the cost of precision
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+
+// bound on the relative rounding error after n operations, n e / (1 - n e)
+// with e half an ulp of one
+pub fn gamma(n: u32) -> Float {
+    let e = n as Float * Float::EPSILON * 0.5;
+    e / (1.0 - e)
+}
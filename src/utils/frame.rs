@@ -0,0 +1,65 @@
+
+use super::aabb::AABB;
+use super::float::{consts::PI, Float};
+use super::vec3::Vec3;
+
+// orthonormal frame for primitives modelled around the local y axis
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub origin: Vec3,
+    pub x: Vec3,
+    pub y: Vec3,
+    pub z: Vec3,
+}
+
+impl Frame {
+    pub fn new(origin: Vec3, axis: Vec3) -> Frame {
+        let y = axis.unit_vector();
+        let helper = if y.x().abs() > 0.9 {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let x = y.cross(helper).unit_vector();
+        let z = x.cross(y);
+        Frame { origin, x, y, z }
+    }
+
+    pub fn to_local(self, p: Vec3) -> Vec3 {
+        self.dir_to_local(p - self.origin)
+    }
+
+    pub fn dir_to_local(self, d: Vec3) -> Vec3 {
+        Vec3::new(d.dot(self.x), d.dot(self.y), d.dot(self.z))
+    }
+
+    pub fn dir_to_world(self, d: Vec3) -> Vec3 {
+        self.x * d.x() + self.y * d.y() + self.z * d.z()
+    }
+
+    pub fn translated(self, offset: Vec3) -> Frame {
+        Frame { origin: self.origin + offset, ..self }
+    }
+
+    // world box around a box given in local coordinates
+    pub fn bounds(self, lower: Vec3, upper: Vec3) -> AABB {
+        let mut close = Vec3::new(Float::INFINITY, Float::INFINITY, Float::INFINITY);
+        let mut far = Vec3::new(Float::NEG_INFINITY, Float::NEG_INFINITY, Float::NEG_INFINITY);
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { lower.x() } else { upper.x() },
+                if i & 2 == 0 { lower.y() } else { upper.y() },
+                if i & 4 == 0 { lower.z() } else { upper.z() },
+            );
+            let p = self.origin + self.dir_to_world(corner);
+            close = Vec3::min(close, p);
+            far = Vec3::max(far, p);
+        }
+        AABB::new(close, far)
+    }
+}
+
+// texture coordinate around the local y axis in [0, 1]
+pub fn azimuth(p: Vec3) -> Float {
+    (p.z().atan2(p.x()) + PI) / (2.0 * PI)
+}
@@ -0,0 +1,412 @@
+use std::borrow::Cow;
+use std::io::Write;
+
+use color_quant::NeuQuant;
+use gif::{DisposalMethod, Encoder, EncodingError, Frame, Repeat};
+use super::float::Float;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Quantizer {
+    MedianCut,
+    NeuQuant,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PaletteMode {
+    // a fresh palette for every frame, flickers the most
+    PerFrame,
+    // one palette built from every frame, needs all frames buffered
+    Global,
+    // reuse the previous palette until it stops fitting the frame
+    Stable,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dither {
+    None,
+    FloydSteinberg,
+    Ordered,
+}
+
+#[derive(Clone, Copy)]
+pub struct GifSettings {
+    pub quantizer: Quantizer,
+    pub palette: PaletteMode,
+    pub dither: Dither,
+    pub fps: Float,
+    pub repeat: Repeat,
+    pub disposal: DisposalMethod,
+}
+
+impl GifSettings {
+    pub fn default() -> GifSettings {
+        GifSettings {
+            quantizer: Quantizer::MedianCut,
+            palette: PaletteMode::Stable,
+            dither: Dither::None,
+            fps: 10.0,
+            repeat: Repeat::Infinite,
+            disposal: DisposalMethod::Any,
+        }
+    }
+
+    // gif delays are stored in hundredths of a second
+    fn delay(&self) -> u16 {
+        (100.0 / self.fps).round().clamp(0.0, u16::MAX as Float) as u16
+    }
+}
+
+const PALETTE_SIZE: usize = 256;
+// the stable palette is rebuilt once a frame quantizes this much worse
+// than the frame the palette was built from
+const STABLE_ERROR_GROWTH: Float = 1.5;
+// colors are binned to 5 bits per channel for median cut and lookups
+const BITS: usize = 5;
+const LEVELS: usize = 1 << BITS;
+
+struct Palette {
+    colors: Vec<[u8; 3]>,
+    // nearest palette entry for every 5 bit color cell
+    lookup: Vec<u8>,
+}
+
+impl Palette {
+    fn build(quantizer: Quantizer, frames: &[&[u8]]) -> Palette {
+        let colors = match quantizer {
+            Quantizer::MedianCut => median_cut(frames, PALETTE_SIZE),
+            Quantizer::NeuQuant => {
+                let rgba: Vec<u8> = frames
+                    .iter()
+                    .flat_map(|f| f.chunks(3).flat_map(|c| [c[0], c[1], c[2], 255]))
+                    .collect();
+                NeuQuant::new(10, PALETTE_SIZE, &rgba)
+                    .color_map_rgb()
+                    .chunks(3)
+                    .map(|c| [c[0], c[1], c[2]])
+                    .collect()
+            }
+        };
+
+        let lookup = (0..LEVELS * LEVELS * LEVELS)
+            .map(|cell| {
+                let center = |shift: usize| (((cell >> shift) & (LEVELS - 1)) << (8 - BITS)) as Float + 4.0;
+                nearest(&colors, [center(2 * BITS), center(BITS), center(0)]) as u8
+            })
+            .collect();
+        Palette { colors, lookup }
+    }
+
+    fn index_of(&self, rgb: [Float; 3]) -> u8 {
+        let bin = |v: Float| (v.clamp(0.0, 255.0) as usize) >> (8 - BITS);
+        self.lookup[(bin(rgb[0]) << (2 * BITS)) | (bin(rgb[1]) << BITS) | bin(rgb[2])]
+    }
+
+    fn rgb(&self) -> Vec<u8> {
+        self.colors.iter().flat_map(|c| c.iter().copied()).collect()
+    }
+
+    // mean squared error of mapping the frame without dithering
+    fn error(&self, rgb: &[u8]) -> Float {
+        let total: Float = rgb
+            .chunks(3)
+            .map(|c| {
+                let p = [c[0] as Float, c[1] as Float, c[2] as Float];
+                distance(self.colors[self.index_of(p) as usize], p)
+            })
+            .sum();
+        total / (rgb.len() / 3) as Float
+    }
+}
+
+fn distance(color: [u8; 3], p: [Float; 3]) -> Float {
+    (0..3).map(|i| (color[i] as Float - p[i]).powi(2)).sum()
+}
+
+fn nearest(colors: &[[u8; 3]], p: [Float; 3]) -> usize {
+    (0..colors.len())
+        .min_by(|&a, &b| distance(colors[a], p).total_cmp(&distance(colors[b], p)))
+        .unwrap_or(0)
+}
+
+// heckbert's median cut over a 5 bit histogram of every frame
+fn median_cut(frames: &[&[u8]], max_colors: usize) -> Vec<[u8; 3]> {
+    let mut histogram = vec![0u32; LEVELS * LEVELS * LEVELS];
+    for frame in frames {
+        for c in frame.chunks(3) {
+            let bin = |v: u8| (v as usize) >> (8 - BITS);
+            histogram[(bin(c[0]) << (2 * BITS)) | (bin(c[1]) << BITS) | bin(c[2])] += 1;
+        }
+    }
+
+    let cells: Vec<([usize; 3], u32)> = histogram
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        .map(|(cell, &count)| {
+            ([cell >> (2 * BITS), (cell >> BITS) & (LEVELS - 1), cell & (LEVELS - 1)], count)
+        })
+        .collect();
+
+    let mut boxes = vec![cells];
+    while boxes.len() < max_colors {
+        // split the box with the widest channel range that can still be split
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (axis, range) = (0..3)
+                    .map(|axis| {
+                        let lo = b.iter().map(|c| c.0[axis]).min().unwrap();
+                        let hi = b.iter().map(|c| c.0[axis]).max().unwrap();
+                        (axis, hi - lo)
+                    })
+                    .max_by_key(|&(_, range)| range)
+                    .unwrap();
+                (i, axis, range)
+            })
+            .max_by_key(|&(_, _, range)| range);
+
+        let (idx, axis, _) = match widest {
+            Some(w) => w,
+            None => break,
+        };
+
+        let mut cells = boxes.swap_remove(idx);
+        cells.sort_by_key(|c| c.0[axis]);
+        let half = cells.iter().map(|c| c.1 as u64).sum::<u64>() / 2;
+        let mut running = 0;
+        let mut split = 1;
+        for (i, c) in cells.iter().enumerate() {
+            running += c.1 as u64;
+            if running >= half {
+                split = (i + 1).clamp(1, cells.len() - 1);
+                break;
+            }
+        }
+        let upper = cells.split_off(split);
+        boxes.push(cells);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|b| {
+            let total: Float = b.iter().map(|c| c.1 as Float).sum();
+            let mean = |axis: usize| {
+                let sum: Float = b.iter().map(|c| ((c.0[axis] << (8 - BITS)) as Float + 4.0) * c.1 as Float).sum();
+                (sum / total).round().clamp(0.0, 255.0) as u8
+            };
+            [mean(0), mean(1), mean(2)]
+        })
+        .collect()
+}
+
+// 8x8 bayer threshold matrix
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+// roughly the spacing between neighbouring palette colors
+const ORDERED_SPREAD: Float = 16.0;
+
+fn index_frame(palette: &Palette, rgb: &[u8], width: usize, dither: Dither) -> Vec<u8> {
+    let pixel = |i: usize| [rgb[3 * i] as Float, rgb[3 * i + 1] as Float, rgb[3 * i + 2] as Float];
+    let count = rgb.len() / 3;
+
+    match dither {
+        Dither::None => (0..count).map(|i| palette.index_of(pixel(i))).collect(),
+        Dither::Ordered => (0..count)
+            .map(|i| {
+                let threshold = BAYER[(i / width) % 8][(i % width) % 8] as Float / 64.0 - 0.5;
+                let p = pixel(i);
+                let offset = threshold * ORDERED_SPREAD;
+                palette.index_of([p[0] + offset, p[1] + offset, p[2] + offset])
+            })
+            .collect(),
+        Dither::FloydSteinberg => {
+            let height = count / width;
+            let mut values: Vec<[Float; 3]> = (0..count).map(pixel).collect();
+            let mut indices = vec![0u8; count];
+
+            for y in 0..height {
+                // serpentine scan avoids the diagonal artifacts of raster order
+                let reverse = y % 2 == 1;
+                for step in 0..width {
+                    let x = if reverse { width - 1 - step } else { step };
+                    let i = y * width + x;
+                    let old = values[i];
+                    let idx = palette.index_of(old);
+                    indices[i] = idx;
+
+                    let chosen = palette.colors[idx as usize];
+                    let err = [old[0] - chosen[0] as Float, old[1] - chosen[1] as Float, old[2] - chosen[2] as Float];
+                    let forward: i64 = if reverse { -1 } else { 1 };
+
+                    let mut spread = |dx: i64, dy: usize, weight: Float| {
+                        let nx = x as i64 + dx;
+                        if nx < 0 || nx >= width as i64 || y + dy >= height {
+                            return;
+                        }
+                        let n = (y + dy) * width + nx as usize;
+                        for c in 0..3 {
+                            values[n][c] += err[c] * weight;
+                        }
+                    };
+                    spread(forward, 0, 7.0 / 16.0);
+                    spread(-forward, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(forward, 1, 1.0 / 16.0);
+                }
+            }
+            indices
+        }
+    }
+}
+
+// quantizes and encodes frames as they arrive, or all at once on
+// finish when the whole animation shares a global palette
+pub struct GifWriter<W: Write> {
+    settings: GifSettings,
+    width: u16,
+    height: u16,
+    out: Option<W>,
+    encoder: Option<Encoder<W>>,
+    pending: Vec<Vec<u8>>,
+    stable: Option<(Palette, Float)>,
+}
+
+impl<W: Write> GifWriter<W> {
+    pub fn new(out: W, width: u16, height: u16, settings: GifSettings) -> GifWriter<W> {
+        GifWriter {
+            settings,
+            width,
+            height,
+            out: Some(out),
+            encoder: None,
+            pending: Vec::new(),
+            stable: None,
+        }
+    }
+
+    pub fn add_frame(&mut self, rgb: Vec<u8>) -> Result<(), EncodingError> {
+        assert_eq!(rgb.len(), self.width as usize * self.height as usize * 3);
+        match self.settings.palette {
+            PaletteMode::Global => {
+                self.pending.push(rgb);
+                Ok(())
+            }
+            PaletteMode::PerFrame => {
+                let palette = Palette::build(self.settings.quantizer, &[&rgb]);
+                self.write(&palette, &rgb, true)
+            }
+            PaletteMode::Stable => {
+                let fits = match &self.stable {
+                    Some((palette, base_error)) => {
+                        palette.error(&rgb) <= base_error.max(1.0) * STABLE_ERROR_GROWTH
+                    }
+                    None => false,
+                };
+                if !fits {
+                    let palette = Palette::build(self.settings.quantizer, &[&rgb]);
+                    let error = palette.error(&rgb);
+                    self.stable = Some((palette, error));
+                }
+
+                let (palette, base_error) = self.stable.take().unwrap();
+                let result = self.write(&palette, &rgb, true);
+                self.stable = Some((palette, base_error));
+                result
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Result<(), EncodingError> {
+        if self.settings.palette == PaletteMode::Global && !self.pending.is_empty() {
+            let frames: Vec<&[u8]> = self.pending.iter().map(|f| f.as_slice()).collect();
+            let palette = Palette::build(self.settings.quantizer, &frames);
+            let pending = std::mem::take(&mut self.pending);
+            for rgb in pending {
+                self.write(&palette, &rgb, false)?;
+            }
+        }
+        // still write a valid header for an animation without frames
+        self.encoder(&[])?;
+        Ok(())
+    }
+
+    fn encoder(&mut self, global_palette: &[u8]) -> Result<&mut Encoder<W>, EncodingError> {
+        if self.encoder.is_none() {
+            let out = self.out.take().unwrap();
+            let mut encoder = Encoder::new(out, self.width, self.height, global_palette)?;
+            encoder.set_repeat(self.settings.repeat)?;
+            self.encoder = Some(encoder);
+        }
+        Ok(self.encoder.as_mut().unwrap())
+    }
+
+    fn write(&mut self, palette: &Palette, rgb: &[u8], local: bool) -> Result<(), EncodingError> {
+        let indices = index_frame(palette, rgb, self.width as usize, self.settings.dither);
+        let frame = Frame {
+            width: self.width,
+            height: self.height,
+            delay: self.settings.delay(),
+            dispose: self.settings.disposal,
+            palette: if local { Some(palette.rgb()) } else { None },
+            buffer: Cow::Owned(indices),
+            ..Frame::default()
+        };
+
+        let global = if local { Vec::new() } else { palette.rgb() };
+        self.encoder(&global)?.write_frame(&frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index_frame, Dither, Palette, Quantizer};
+
+    const DITHERS: [Dither; 3] = [Dither::None, Dither::FloydSteinberg, Dither::Ordered];
+
+    // every color of a 64x64 rgb gradient, far more than fit in a gif palette
+    fn gradient() -> Vec<u8> {
+        (0..64 * 64)
+            .flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, ((i * 7) % 256) as u8])
+            .collect()
+    }
+
+    #[test]
+    fn palette_fits_in_a_gif() {
+        let rgb = gradient();
+        for quantizer in [Quantizer::MedianCut, Quantizer::NeuQuant] {
+            let palette = Palette::build(quantizer, &[&rgb]);
+            assert!(!palette.colors.is_empty() && palette.colors.len() <= 256, "{:?}", quantizer);
+            for dither in DITHERS {
+                let indices = index_frame(&palette, &rgb, 64, dither);
+                assert_eq!(indices.len(), 64 * 64);
+                assert!(indices.iter().all(|&i| (i as usize) < palette.colors.len()), "{:?} {:?}", quantizer, dither);
+            }
+        }
+    }
+
+    // with only a few well separated colors, each on a bin center, median
+    // cut keeps them exactly and no dither has any error to spread
+    #[test]
+    fn few_colors_survive_exactly() {
+        let colors = [[4, 4, 4], [252, 252, 252], [228, 36, 60], [20, 148, 92], [100, 60, 204]];
+        let rgb: Vec<u8> = (0..40 * 30).flat_map(|i| colors[(i * 7 / 5 + i / 40) % colors.len()]).collect();
+        let palette = Palette::build(Quantizer::MedianCut, &[&rgb]);
+        assert_eq!(palette.colors.len(), colors.len());
+        for dither in DITHERS {
+            let indices = index_frame(&palette, &rgb, 40, dither);
+            let back: Vec<u8> = indices.iter().flat_map(|&i| palette.colors[i as usize]).collect();
+            assert_eq!(back, rgb, "{:?}", dither);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::sync::Arc;
+
+use super::aabb::AABB;
+use super::float::Float;
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::netpbm::GrayImage;
+use super::object::Object;
+use super::ray::Ray;
+use super::scatter::Scatter;
+use super::vec3::Vec3;
+
+// maximum height over square blocks of cells, level k has blocks of 2^k
+struct MaxLevel {
+    width: usize,
+    depth: usize,
+    max: Vec<Float>,
+}
+
+// grid of heights, each cell split into two triangles along its diagonal.
+// the samples are shared so stepping frames does not copy them
+#[derive(Clone)]
+pub struct Heightfield {
+    values: Arc<Vec<Float>>,
+    levels: Arc<Vec<MaxLevel>>,
+    lowest: Float,
+    // number of samples along x and z
+    nx: usize,
+    nz: usize,
+    // corner at the lowest x and z, heights are added to its y
+    origin: Vec3,
+    size_x: Float,
+    size_z: Float,
+    height: Float,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Heightfield {
+    // values are row major with x varying fastest, normally in [0, 1]
+    pub fn new(values: Vec<Float>, nx: usize, nz: usize, m: Arc<dyn Scatter>) -> Heightfield {
+        assert!(nx >= 2 && nz >= 2 && values.len() == nx * nz);
+        let lowest = values.iter().cloned().fold(Float::INFINITY, Float::min);
+
+        let (cw, cd) = (nx - 1, nz - 1);
+        let mut max = Vec::with_capacity(cw * cd);
+        for j in 0..cd {
+            for i in 0..cw {
+                let corners = [
+                    values[j * nx + i],
+                    values[j * nx + i + 1],
+                    values[(j + 1) * nx + i],
+                    values[(j + 1) * nx + i + 1],
+                ];
+                max.push(corners.iter().cloned().fold(Float::NEG_INFINITY, Float::max));
+            }
+        }
+        let mut levels = vec![MaxLevel { width: cw, depth: cd, max }];
+        while levels.last().is_some_and(|l| l.width > 1 || l.depth > 1) {
+            let prev = levels.last().unwrap();
+            let (width, depth) = (prev.width.div_ceil(2), prev.depth.div_ceil(2));
+            let mut max = vec![Float::NEG_INFINITY; width * depth];
+            for j in 0..prev.depth {
+                for i in 0..prev.width {
+                    let m = &mut max[(j / 2) * width + i / 2];
+                    *m = m.max(prev.max[j * prev.width + i]);
+                }
+            }
+            levels.push(MaxLevel { width, depth, max });
+        }
+
+        Heightfield {
+            values: Arc::new(values),
+            levels: Arc::new(levels),
+            lowest,
+            nx,
+            nz,
+            origin: Vec3::new(0.0, 0.0, 0.0),
+            size_x: 1.0,
+            size_z: 1.0,
+            height: 1.0,
+            mat: m,
+        }
+    }
+
+    // image rows run along z, brighter is higher
+    pub fn from_image(image: &GrayImage, m: Arc<dyn Scatter>) -> Heightfield {
+        Heightfield::new(image.values.clone(), image.width, image.height, m)
+    }
+
+    // where the field sits in the world and how tall a value of one is
+    pub fn with_extent(mut self, origin: Vec3, size_x: Float, size_z: Float, height: Float) -> Heightfield {
+        self.origin = origin;
+        self.size_x = size_x;
+        self.size_z = size_z;
+        self.height = height.max(1e-9);
+        self
+    }
+
+    fn value(&self, i: usize, j: usize) -> Float {
+        self.values[j.min(self.nz - 1) * self.nx + i.min(self.nx - 1)]
+    }
+
+    // world space cell size along x and z
+    fn cell(&self) -> (Float, Float) {
+        (self.size_x / (self.nx - 1) as Float, self.size_z / (self.nz - 1) as Float)
+    }
+
+    // surface height below a world position, for placing things on the ground
+    pub fn height_at(&self, x: Float, z: Float) -> Option<Float> {
+        let (cx, cz) = self.cell();
+        let gx = (x - self.origin.x()) / cx;
+        let gz = (z - self.origin.z()) / cz;
+        if gx < 0.0 || gz < 0.0 || gx > (self.nx - 1) as Float || gz > (self.nz - 1) as Float {
+            return None;
+        }
+        let i = (gx as usize).min(self.nx - 2);
+        let j = (gz as usize).min(self.nz - 2);
+        let (fx, fz) = (gx - i as Float, gz - j as Float);
+        let v00 = self.value(i, j);
+        let v10 = self.value(i + 1, j);
+        let v01 = self.value(i, j + 1);
+        let v11 = self.value(i + 1, j + 1);
+        let v = if fx >= fz {
+            v00 + fx * (v10 - v00) + fz * (v11 - v10)
+        } else {
+            v00 + fz * (v01 - v00) + fx * (v11 - v01)
+        };
+        Some(self.origin.y() + v * self.height)
+    }
+
+    // smooth normal at a sample from central differences
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (cx, cz) = self.cell();
+        let (il, ir) = (i.saturating_sub(1), (i + 1).min(self.nx - 1));
+        let (jl, jr) = (j.saturating_sub(1), (j + 1).min(self.nz - 1));
+        let dx = (self.value(ir, j) - self.value(il, j)) * self.height / ((ir - il) as Float * cx);
+        let dz = (self.value(i, jr) - self.value(i, jl)) * self.height / ((jr - jl) as Float * cz);
+        Vec3::new(-dx, 1.0, -dz)
+    }
+
+    // intersects the two triangles of a cell in grid space, returning the
+    // hit distance and the position within the cell
+    fn hit_cell(&self, o: Vec3, d: Vec3, i: usize, j: usize, t_min: Float, t_max: Float) -> Option<(Float, Float, Float)> {
+        let v00 = self.value(i, j);
+        let v10 = self.value(i + 1, j);
+        let v01 = self.value(i, j + 1);
+        let v11 = self.value(i + 1, j + 1);
+        let (ox, oz) = (o.x() - i as Float, o.z() - j as Float);
+
+        let mut best: Option<(Float, Float, Float)> = None;
+        // each triangle is the plane y = v00 + a x + b z over half the cell
+        for (a, b, upper) in [(v10 - v00, v11 - v10, true), (v11 - v01, v01 - v00, false)] {
+            let denom = d.y() - a * d.x() - b * d.z();
+            if denom == 0.0 {
+                continue;
+            }
+            let t = (v00 + a * ox + b * oz - o.y()) / denom;
+            if t < t_min || t > best.map_or(t_max, |h| h.0) {
+                continue;
+            }
+            let (fx, fz) = (ox + d.x() * t, oz + d.z() * t);
+            let inside = (0.0..=1.0).contains(&fx) && (0.0..=1.0).contains(&fz);
+            if inside && (fx >= fz) == upper {
+                best = Some((t, fx, fz));
+            }
+        }
+        best
+    }
+}
+
+// entry distance of a ray into a box, None when it misses
+fn enter(o: Vec3, inv: Vec3, lower: Vec3, upper: Vec3, t_min: Float, t_max: Float) -> Option<Float> {
+    let (mut t0, mut t1) = (t_min, t_max);
+    for axis in 0..3 {
+        let a = (lower.values[axis] - o.values[axis]) * inv.values[axis];
+        let b = (upper.values[axis] - o.values[axis]) * inv.values[axis];
+        t0 = t0.max(a.min(b));
+        t1 = t1.min(a.max(b));
+    }
+    if t0 <= t1 { Some(t0) } else { None }
+}
+
+impl Hit for Heightfield {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // grid space has unit cells and unscaled values, distances along
+        // the ray stay the same
+        let (cx, cz) = self.cell();
+        let scale = Vec3::new(1.0 / cx, 1.0 / self.height, 1.0 / cz);
+        let rel = r.origin() - self.origin;
+        let o = Vec3::new(rel.x() * scale.x(), rel.y() * scale.y(), rel.z() * scale.z());
+        let dir = r.direction();
+        let d = Vec3::new(dir.x() * scale.x(), dir.y() * scale.y(), dir.z() * scale.z());
+        let inv = Vec3::new(1.0 / d.x(), 1.0 / d.y(), 1.0 / d.z());
+
+        // walk the max mip front to back, skipping blocks the ray passes over
+        let top = self.levels.len() - 1;
+        let mut stack = Vec::with_capacity(4 * self.levels.len());
+        stack.push((t_min, top, 0, 0));
+        let mut closest = t_max;
+        let mut best = None;
+        while let Some((t_enter, level, i, j)) = stack.pop() {
+            if t_enter > closest {
+                continue;
+            }
+            if level == 0 {
+                if let Some(h) = self.hit_cell(o, d, i, j, t_min, closest) {
+                    closest = h.0;
+                    best = Some((h, i, j));
+                }
+                continue;
+            }
+
+            let below = &self.levels[level - 1];
+            let size = (1usize << (level - 1)) as Float;
+            let mut children = [(Float::INFINITY, 0, 0, 0); 4];
+            let mut count = 0;
+            for (ci, cj) in [(2 * i, 2 * j), (2 * i + 1, 2 * j), (2 * i, 2 * j + 1), (2 * i + 1, 2 * j + 1)] {
+                if ci >= below.width || cj >= below.depth {
+                    continue;
+                }
+                let lower = Vec3::new(ci as Float * size, self.lowest, cj as Float * size);
+                let upper = Vec3::new(
+                    ((ci + 1) as Float * size).min((self.nx - 1) as Float),
+                    below.max[cj * below.width + ci],
+                    ((cj + 1) as Float * size).min((self.nz - 1) as Float),
+                );
+                if let Some(t) = enter(o, inv, lower, upper, t_min, closest) {
+                    children[count] = (t, level - 1, ci, cj);
+                    count += 1;
+                }
+            }
+            // nearest child ends up on top of the stack
+            let children = &mut children[..count];
+            children.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+            stack.extend_from_slice(children);
+        }
+
+        let ((t, fx, fz), i, j) = best?;
+        // plane of the triangle that was hit, scaled back to world space
+        let (a, b) = if fx >= fz {
+            (self.value(i + 1, j) - self.value(i, j), self.value(i + 1, j + 1) - self.value(i + 1, j))
+        } else {
+            (self.value(i + 1, j + 1) - self.value(i, j + 1), self.value(i, j + 1) - self.value(i, j))
+        };
+        let geometric = Vec3::new(-a * self.height / cx, 1.0, -b * self.height / cz);
+        let n00 = self.vertex_normal(i, j);
+        let n10 = self.vertex_normal(i + 1, j);
+        let n01 = self.vertex_normal(i, j + 1);
+        let n11 = self.vertex_normal(i + 1, j + 1);
+        let normal = if fx >= fz {
+            n00 * (1.0 - fx) + n10 * (fx - fz) + n11 * fz
+        } else {
+            n00 * (1.0 - fz) + n01 * (fz - fx) + n11 * fx
+        };
+        let u = (i as Float + fx) / (self.nx - 1) as Float;
+        let v = (j as Float + fz) / (self.nz - 1) as Float;
+        let mut rec = HitRecord::new(r, t, geometric.unit_vector(), self.mat.as_ref(), (u, v));
+        rec.set_shading_normal(normal.unit_vector());
+        Some(rec)
+    }
+}
+
+impl Object for Heightfield {
+    fn bounding_box(&self) -> AABB {
+        let highest = self.levels.last().unwrap().max[0];
+        let lower = self.origin + Vec3::new(0.0, self.lowest * self.height, 0.0);
+        let upper = self.origin + Vec3::new(self.size_x, highest * self.height, self.size_z);
+        // keep flat fields from collapsing to a plane
+        AABB::new(lower - Vec3::new(0.0, 1e-4, 0.0), upper + Vec3::new(0.0, 1e-4, 0.0))
+    }
+
+    // terrain does not move
+    fn step_frame(&self, _time_delta: Float) -> Box<dyn Object> {
+        Box::new(self.clone())
+    }
+
+    fn material(&self) -> Arc<dyn Scatter> {
+        self.mat.clone()
+    }
+
+    fn movement(&self) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+}
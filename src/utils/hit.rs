@@ -1,6 +1,19 @@
+use super::float::Float;
 use super::hit_record::HitRecord;
 use super::ray::Ray;
 
 pub trait Hit : Send + Sync {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>>;
+
+    // whether anything lies along the ray between t_min and t_max, may stop
+    // at the first hit found and skip building a record
+    fn occluded(&self, r: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.hit(r, t_min, t_max).is_some()
+    }
+
+    // closest hit for each of a bundle of rays, structures that can share
+    // work between coherent rays override this
+    fn hit_packet(&self, rays: &[Ray], t_min: Float, t_max: Float) -> Vec<Option<HitRecord<'_>>> {
+        rays.iter().map(|r| self.hit(r, t_min, t_max)).collect()
+    }
 }
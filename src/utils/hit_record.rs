@@ -1,17 +1,48 @@
+use super::float::{gamma, Float};
 use super::ray::Ray;
 use super::scatter::Scatter;
 use super::vec3::Vec3;
-use std::sync::Arc;
 
-pub struct HitRecord {
+// the material is borrowed from the object that was hit, so testing an
+// object never touches a reference count
+pub struct HitRecord<'a> {
     pub p: Vec3,
+    // normal for shading, facing the ray
     pub normal: Vec3,
-    pub mat: Arc<dyn Scatter>,
-    pub t: f64,
+    // normal of the surface that was actually hit, also facing the ray.
+    // differs from normal where a primitive interpolates its normals
+    pub geometric_normal: Vec3,
+    pub mat: &'a dyn Scatter,
+    pub t: Float,
     pub front_face: bool,
+    pub object_id: usize,
+    // surface parameterization, both in [0, 1], nothing is textured yet
+    pub u: Float,
+    pub v: Float,
+    // bound on the rounding error in each coordinate of p
+    pub p_error: Float,
 }
 
-impl HitRecord {
+impl<'a> HitRecord<'a> {
+    pub fn new(r: &Ray, t: Float, outward_normal: Vec3, mat: &'a dyn Scatter, uv: (Float, Float)) -> HitRecord<'a> {
+        let mut rec = HitRecord {
+            p: r.at(t),
+            normal: outward_normal,
+            geometric_normal: outward_normal,
+            mat,
+            t,
+            front_face: false,
+            object_id: 0,
+            u: uv.0,
+            v: uv.1,
+            // loose enough for the closed form solvers, primitives that know
+            // their error better overwrite it
+            p_error: gamma(32) * (r.origin().length() + r.direction().length() * t.abs()),
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
         self.front_face = r.direction().dot(outward_normal) < 0.0;
         self.normal = if self.front_face {
@@ -19,5 +50,152 @@ impl HitRecord {
         } else {
             outward_normal * -1.0
         };
+        self.geometric_normal = self.normal;
+    }
+
+    // replaces the shading normal only, the side stays the one the
+    // geometric normal gave
+    pub fn set_shading_normal(&mut self, outward_normal: Vec3) {
+        self.normal = if self.front_face {
+            outward_normal
+        } else {
+            outward_normal * -1.0
+        };
+    }
+
+    // ray leaving the surface, its origin is pushed along the geometric
+    // normal to the side it heads for, far enough that p_error cannot put
+    // it back behind the surface it just left
+    pub fn spawn(&self, direction: Vec3) -> Ray {
+        let n = self.geometric_normal;
+        let offset = n * (self.p_error * (n.x().abs() + n.y().abs() + n.z().abs()));
+        let origin = if direction.dot(n) > 0.0 { self.p + offset } else { self.p - offset };
+        Ray::new(origin, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::utils::aabb::AABB;
+    use crate::utils::color::Color;
+    use crate::utils::cone::Cone;
+    use crate::utils::csg::Csg;
+    use crate::utils::cylinder::Cylinder;
+    use crate::utils::disk::Disk;
+    use crate::utils::float::Float;
+    use crate::utils::heightfield::Heightfield;
+    use crate::utils::hit::Hit;
+    use crate::utils::lambertian::Lambertian;
+    use crate::utils::noise::noise_image;
+    use crate::utils::object::Object;
+    use crate::utils::ray::Ray;
+    use crate::utils::sdf::{RoundedBox, Sdf, SdfObject, SmoothUnion, Twist};
+    use crate::utils::sphere::Sphere;
+    use crate::utils::torus::Torus;
+    use crate::utils::vec3::Vec3;
+
+    fn random_direction(rng: &mut StdRng) -> Vec3 {
+        loop {
+            let d = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            if d.length_squared() > 1e-4 && d.length_squared() <= 1.0 {
+                return d;
+            }
+        }
+    }
+
+    // shoots rays from around the object at points in its box. every hit
+    // spawns one ray away from the surface and one into it, neither may
+    // hit the same surface again right where it starts. close to an edge
+    // the ray may rightly hit the neighbouring cap or triangle
+    fn check_spawns(object: &dyn Object, rng: &mut StdRng) {
+        let aabb = object.bounding_box();
+        let (lower, upper) = (aabb.close_corner, aabb.far_corner);
+        let center = (lower + upper) * 0.5;
+        let size = (upper - lower).length();
+        let mut hits = 0;
+        while hits < 2000 {
+            let target = Vec3::new(
+                rng.gen_range(lower.x()..upper.x()),
+                rng.gen_range(lower.y()..upper.y()),
+                rng.gen_range(lower.z()..upper.z()),
+            );
+            let origin = center + random_direction(rng).unit_vector() * size;
+            let Some(rec) = object.hit(&Ray::new(origin, target - origin), 0.0, Float::INFINITY) else {
+                continue;
+            };
+            hits += 1;
+            let away = random_direction(rng);
+            let n = rec.geometric_normal;
+            for direction in [if away.dot(n) > 0.0 { away } else { away * -1.0 }, n * -1.0 + away * 0.5] {
+                let out = rec.spawn(direction);
+                if let Some(again) = object.hit(&out, 0.0, Float::INFINITY) {
+                    let moved = (again.p - rec.p).length();
+                    let same_surface = again.geometric_normal.dot(n).abs() > 0.99;
+                    assert!(moved > 1e-3 || !same_surface, "hit again {} from {:?}", moved, rec.p.values);
+                }
+            }
+        }
+    }
+
+    // a ground sphere like the one in the default scene is where a fixed
+    // t_min breaks down first, rays spawned off it must never hit it again
+    // right where they start
+    #[test]
+    fn spawned_rays_clear_the_surface() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let ground = Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, mat.clone(), Vec3::new(0.0, 0.0, 0.0));
+        let mut rng = StdRng::seed_from_u64(6);
+        for _ in 0..10000 {
+            let origin = Vec3::new(13.0, 2.0, 3.0) + random_direction(&mut rng) * 2.0;
+            let r = Ray::new(origin, Vec3::new(0.0, -1.0, 0.0) + random_direction(&mut rng) * 0.9);
+            let rec = ground.hit(&r, 0.0, Float::INFINITY).unwrap();
+            let away = random_direction(&mut rng);
+            let out = rec.spawn(if away.dot(rec.normal) > 0.0 { away } else { away * -1.0 });
+            assert!(ground.hit(&out, 0.0, Float::INFINITY).is_none());
+            // going in, the next hit is the far side of the sphere
+            let inside = rec.spawn(rec.normal * -1.0 + away * 0.5);
+            assert!(ground.hit(&inside, 0.0, Float::INFINITY).unwrap().t > 1.0);
+        }
+
+        // the heightfield shades with interpolated normals, spawning has to
+        // go by the triangles
+        let terrain = Heightfield::from_image(&noise_image(64, 64, 8.0, 3), mat.clone())
+            .with_extent(Vec3::new(-32.0, 0.0, -32.0), 64.0, 64.0, 12.0);
+        let tilted = Vec3::new(1.0, 2.0, 0.5);
+        let objects: [Box<dyn Object>; 4] = [
+            Box::new(terrain),
+            Box::new(Cone::new(Vec3::new(1.0, 2.0, 3.0), tilted, 1.5, 3.0, mat.clone(), Vec3::new(0.0, 0.0, 0.0))),
+            Box::new(Cylinder::new(Vec3::new(1.0, 2.0, 3.0), tilted, 1.5, 3.0, mat.clone(), Vec3::new(0.0, 0.0, 0.0))),
+            Box::new(Torus::new(Vec3::new(1.0, 2.0, 3.0), tilted, 2.0, 0.5, mat.clone(), Vec3::new(0.0, 0.0, 0.0))),
+        ];
+        for object in &objects {
+            check_spawns(object.as_ref(), &mut rng);
+        }
+
+        // marched surfaces stop short of the surface, booleans report the
+        // children's records and disks are flat on both sides
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let still = Vec3::new(0.0, 0.0, 0.0);
+        let sdf = |sdf: Arc<dyn Sdf>, half: Vec3| SdfObject::new(sdf, center, AABB::new(half * -1.0, half), mat.clone(), still);
+        let slab = Arc::new(RoundedBox { half_extents: Vec3::new(1.0, 2.0, 0.25), radius: 0.1 });
+        let ball = |x: Float| -> Arc<dyn Sdf> { Arc::new(move |p: Vec3| (p - Vec3::new(x, 0.0, 0.0)).length() - 1.0) };
+        let sphere = |x: Float, radius: Float| -> Box<dyn Object> { Box::new(Sphere::new(center + Vec3::new(x, 0.0, 0.0), radius, mat.clone(), still)) };
+        let objects: [Box<dyn Object>; 7] = [
+            Box::new(sdf(slab.clone(), Vec3::new(1.0, 2.0, 0.25))),
+            Box::new(sdf(Arc::new(Twist { inner: slab, rate: 1.0 }), Vec3::new(1.0, 2.0, 1.0)).with_step_scale(0.5)),
+            Box::new(sdf(Arc::new(SmoothUnion { a: ball(-0.8), b: ball(0.8), k: 0.5 }), Vec3::new(2.0, 1.5, 1.5))),
+            Box::new(Csg::difference(sphere(0.0, 2.0), sphere(0.5, 1.0))),
+            Box::new(Csg::intersection(sphere(-0.5, 1.0), sphere(0.5, 1.0))),
+            Box::new(Disk::new(center, tilted, 2.0, mat.clone(), still)),
+            Box::new(Disk::annulus(center, tilted, 1.0, 2.0, mat.clone(), still)),
+        ];
+        for object in &objects {
+            check_spawns(object.as_ref(), &mut rng);
+        }
     }
 }
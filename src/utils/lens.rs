@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use super::float::{consts::PI, Float};
+use super::netpbm::GrayImage;
+
+// maps two uniform numbers to the unit disk without the clumping of
+// polar sampling (shirley and chiu 1997)
+pub fn concentric_disk(u1: Float, u2: Float) -> (Float, Float) {
+    let a = 2.0 * u1 - 1.0;
+    let b = 2.0 * u2 - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+// image used as the aperture, samples land where it is bright
+pub struct BokehMask {
+    width: usize,
+    height: usize,
+    // running sum of pixel values for inverse transform sampling
+    cdf: Vec<Float>,
+}
+
+impl BokehMask {
+    pub fn new(image: &GrayImage) -> Option<BokehMask> {
+        let mut cdf = Vec::with_capacity(image.values.len());
+        let mut total = 0.0;
+        for &v in &image.values {
+            total += v.max(0.0);
+            cdf.push(total);
+        }
+        if total <= 0.0 {
+            return None;
+        }
+        for c in cdf.iter_mut() {
+            *c /= total;
+        }
+        Some(BokehMask { width: image.width, height: image.height, cdf })
+    }
+
+    // point in [-1, 1]^2, the longer image side spans the full range
+    fn sample(&self, rng: &mut impl Rng) -> (Float, Float) {
+        let target: Float = rng.gen();
+        let idx = self.cdf.partition_point(|&c| c < target).min(self.cdf.len() - 1);
+        let x = (idx % self.width) as Float + rng.gen::<Float>();
+        let y = (idx / self.width) as Float + rng.gen::<Float>();
+
+        let size = self.width.max(self.height) as Float;
+        let x = (2.0 * x - self.width as Float) / size;
+        let y = (self.height as Float - 2.0 * y) / size;
+        (x, y)
+    }
+}
+
+#[derive(Clone)]
+pub enum ApertureShape {
+    Circle,
+    // regular polygon made of straight blades, rotation in degrees
+    Polygon { blades: u32, rotation: Float },
+    Image(Arc<BokehMask>),
+}
+
+// lens settings that stay fixed while the aperture size is animated
+#[derive(Clone)]
+pub struct Lens {
+    pub shape: ApertureShape,
+    // how far a second stop in front of the aperture shifts towards the
+    // image corners, 0 turns cat's eye vignetting off
+    pub cats_eye: Float,
+}
+
+impl Lens {
+    pub fn default() -> Lens {
+        Lens { shape: ApertureShape::Circle, cats_eye: 0.0 }
+    }
+
+    // point on the unit aperture, None if a lens stop blocks it.
+    // (sx, sy) is the image position in [-1, 1]^2 used for vignetting
+    pub fn sample(&self, sx: Float, sy: Float) -> Option<(Float, Float)> {
+        let mut rng = rand::thread_rng();
+        let (x, y) = match &self.shape {
+            ApertureShape::Circle => concentric_disk(rng.gen(), rng.gen()),
+            ApertureShape::Polygon { blades, rotation } => {
+                sample_polygon(*blades, rotation.to_radians(), &mut rng)
+            }
+            ApertureShape::Image(mask) => mask.sample(&mut rng),
+        };
+
+        // the second stop is a unit circle pushed outwards with the image position
+        let (cx, cy) = (sx * self.cats_eye, sy * self.cats_eye);
+        if self.cats_eye > 0.0 && (x - cx).powi(2) + (y - cy).powi(2) > 1.0 {
+            return None;
+        }
+        Some((x, y))
+    }
+}
+
+// uniform point in a regular polygon inscribed in the unit circle
+fn sample_polygon(blades: u32, rotation: Float, rng: &mut impl Rng) -> (Float, Float) {
+    let blades = blades.max(3);
+    let step = 2.0 * PI / blades as Float;
+    // every blade edge forms an equal triangle with the center
+    let i = rng.gen_range(0..blades) as Float;
+    let a0 = rotation + step * i;
+    let a1 = a0 + step;
+
+    let (mut s, mut t): (Float, Float) = (rng.gen(), rng.gen());
+    if s + t > 1.0 {
+        s = 1.0 - s;
+        t = 1.0 - t;
+    }
+    (s * a0.cos() + t * a1.cos(), s * a0.sin() + t * a1.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{concentric_disk, sample_polygon, ApertureShape, Lens};
+    use crate::utils::float::{consts::PI, Float};
+
+    #[test]
+    fn disk_samples_stay_in_the_unit_circle() {
+        assert_eq!(concentric_disk(0.5, 0.5), (0.0, 0.0));
+        for j in 0..=64 {
+            for i in 0..=64 {
+                let (x, y) = concentric_disk(i as Float / 64.0, j as Float / 64.0);
+                assert!(x * x + y * y <= 1.0 + 1e-5, "({}, {}) maps to ({}, {})", i, j, x, y);
+            }
+        }
+    }
+
+    // inside the polygon means no further along any edge normal than the
+    // apothem
+    #[test]
+    fn polygon_samples_stay_inside_the_blades() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for blades in 3..9 {
+            let rotation: Float = 0.3 * blades as Float;
+            let step = 2.0 * PI / blades as Float;
+            for _ in 0..2000 {
+                let (x, y) = sample_polygon(blades, rotation, &mut rng);
+                for k in 0..blades {
+                    let mid = rotation + step * (k as Float + 0.5);
+                    assert!(x * mid.cos() + y * mid.sin() <= (step / 2.0).cos() + 1e-5, "{} blades ({}, {})", blades, x, y);
+                }
+            }
+        }
+    }
+
+    // the cat's eye stop only ever removes samples, and removes none at
+    // the image center
+    #[test]
+    fn cats_eye_clips_to_the_shifted_stop() {
+        let lens = Lens { shape: ApertureShape::Polygon { blades: 6, rotation: 15.0 }, cats_eye: 0.6 };
+        let mut blocked = 0;
+        for _ in 0..2000 {
+            assert!(lens.sample(0.0, 0.0).is_some());
+            match lens.sample(1.0, -1.0) {
+                Some((x, y)) => {
+                    assert!(x * x + y * y <= 1.0 + 1e-5);
+                    assert!((x - 0.6).powi(2) + (y + 0.6).powi(2) <= 1.0 + 1e-5);
+                }
+                None => blocked += 1,
+            }
+        }
+        assert!(blocked > 0 && blocked < 2000);
+    }
+}
@@ -0,0 +1,124 @@
+use super::color::Color;
+use super::dielectric::{refract_or_reflect, Ior};
+use super::float::Float;
+use super::hit_record::HitRecord;
+use super::ray::Ray;
+use super::spectrum::rgb_to_spectrum;
+
+// interior of a closed dielectric, absorption is per unit of distance
+#[derive(Clone, Copy)]
+pub struct Medium {
+    pub ior: Ior,
+    pub absorption: Color,
+    // where media overlap the one with the highest priority wins, so a
+    // liquid can be modelled slightly larger than its container
+    pub priority: u32,
+}
+
+impl Medium {
+    // beer-lambert falloff over a distance travelled inside the medium
+    pub fn transmittance(&self, distance: Float) -> Color {
+        let [r, g, b] = self.absorption.values();
+        Color::new(
+            (-r * distance).exp(),
+            (-g * distance).exp(),
+            (-b * distance).exp(),
+        )
+    }
+
+    pub fn transmittance_spectral(&self, distance: Float, lambda: Float) -> Float {
+        (-rgb_to_spectrum(self.absorption, lambda) * distance).exp()
+    }
+}
+
+// media the current path is inside of, keyed by object id
+pub struct MediumStack {
+    entries: Vec<(usize, Medium)>,
+}
+
+impl MediumStack {
+    pub fn default() -> MediumStack {
+        MediumStack { entries: Vec::new() }
+    }
+
+    // the medium the ray is travelling through, ties go to the most
+    // recently entered one
+    pub fn current(&self) -> Option<&Medium> {
+        // max_by_key keeps the last of equal maxima
+        self.entries
+            .iter()
+            .max_by_key(|(_, m)| m.priority)
+            .map(|(_, m)| m)
+    }
+
+    fn ior(&self, lambda: Float) -> Float {
+        self.current().map_or(1.0, |m| m.ior.at(lambda))
+    }
+
+    fn remove(&mut self, object_id: usize) {
+        if let Some(i) = self.entries.iter().rposition(|(id, _)| *id == object_id) {
+            self.entries.remove(i);
+        }
+    }
+
+    // continues a path across the boundary of a medium, surfaces that lose
+    // to a higher priority medium are passed through unchanged
+    pub fn cross(&mut self, r_in: &Ray, rec: &HitRecord, medium: Medium, lambda: Float) -> Ray {
+        if rec.front_face {
+            let top = self.current().map(|m| m.priority);
+            if top.is_some_and(|p| p > medium.priority) {
+                self.entries.push((rec.object_id, medium));
+                return rec.spawn(r_in.direction());
+            }
+            let (scattered, refracted) =
+                refract_or_reflect(r_in, rec, self.ior(lambda) / medium.ior.at(lambda));
+            if refracted {
+                self.entries.push((rec.object_id, medium));
+            }
+            scattered
+        } else {
+            let mut outside = MediumStack {
+                entries: self.entries.clone(),
+            };
+            outside.remove(rec.object_id);
+            if outside.current().is_some_and(|m| m.priority > medium.priority) {
+                *self = outside;
+                return rec.spawn(r_in.direction());
+            }
+            let (scattered, refracted) =
+                refract_or_reflect(r_in, rec, medium.ior.at(lambda) / outside.ior(lambda));
+            if refracted {
+                *self = outside;
+            }
+            scattered
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Medium, MediumStack};
+    use crate::utils::color::Color;
+    use crate::utils::dielectric::Ior;
+    use crate::utils::float::Float;
+
+    fn medium(n: Float, priority: u32) -> Medium {
+        Medium { ior: Ior::Constant(n), absorption: Color::new(0.0, 0.0, 0.0), priority }
+    }
+
+    #[test]
+    fn innermost_medium_wins_ties() {
+        let mut media = MediumStack::default();
+        assert!(media.current().is_none());
+        // glass nested in water, both at the default priority
+        media.entries.push((0, medium(1.33, 0)));
+        media.entries.push((1, medium(1.5, 0)));
+        assert_eq!(media.ior(589.0), 1.5);
+        // a higher priority still wins over what was entered later
+        media.entries.push((2, medium(1.2, 0)));
+        media.entries[0].1.priority = 1;
+        assert_eq!(media.ior(589.0), 1.33);
+        media.remove(0);
+        assert_eq!(media.ior(589.0), 1.2);
+    }
+}
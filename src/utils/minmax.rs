@@ -1,7 +1,9 @@
-pub fn min(a: f64, b: f64) -> f64 {
+use super::float::Float;
+
+pub fn min(a: Float, b: Float) -> Float {
     a.min(b)
 }
 
-pub fn max(a: f64, b: f64) -> f64 {
+pub fn max(a: Float, b: Float) -> Float {
     a.max(b)
 }
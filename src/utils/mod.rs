@@ -1,13 +1,48 @@
+pub mod aov;
+pub mod bench;
+pub mod bvh;
 pub mod camera;
+pub mod camera_model;
+pub mod camera_path;
 pub mod color;
+pub mod cone;
+pub mod csg;
+pub mod cylinder;
+pub mod debug_view;
+pub mod denoise;
 pub mod dielectric;
+pub mod disk;
+pub mod exr;
+pub mod film;
+pub mod filter;
+pub mod float;
+pub mod frame;
+pub mod gif_output;
+pub mod heightfield;
 pub mod hit;
 pub mod hit_record;
 pub mod lambertian;
+pub mod lens;
 pub mod metal;
+pub mod options;
+pub mod packet;
+pub mod png;
+pub mod query;
 pub mod ray;
+pub mod roots;
+pub mod sah;
 pub mod scatter;
+pub mod sdf;
+pub mod sequence;
+pub mod spectrum;
 pub mod sphere;
+pub mod torus;
 pub mod vec3;
+pub mod wide_bvh;
+pub mod y4m;
 pub mod aabb;
 pub mod minmax;
+pub mod medium;
+pub mod netpbm;
+pub mod noise;
+pub mod object;
@@ -0,0 +1,98 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+use super::float::Float;
+
+// grayscale image with values normalized to [0, 1], top row first
+pub struct GrayImage {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<Float>,
+}
+
+impl GrayImage {
+    pub fn get(&self, x: usize, y: usize) -> Float {
+        self.values[y * self.width + x]
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+// reads ascii (P2) or binary (P5) pgm files
+pub fn read_pgm(path: &Path) -> io::Result<GrayImage> {
+    let data = fs::read(path)?;
+    let mut pos = 0;
+
+    // header tokens are separated by whitespace and may have # comments
+    let mut token = || -> io::Result<String> {
+        loop {
+            while pos < data.len() && data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos < data.len() && data[pos] == b'#' {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            break;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid("unexpected end of pgm file"));
+        }
+        Ok(String::from_utf8_lossy(&data[start..pos]).into_owned())
+    };
+
+    let number = |s: String| s.parse::<usize>().map_err(|_| invalid("bad number in pgm header"));
+
+    let magic = token()?;
+    let width = number(token()?)?;
+    let height = number(token()?)?;
+    let max_value = number(token()?)?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("bad pgm max value"));
+    }
+
+    let count = width * height;
+    let values: Vec<Float> = match magic.as_str() {
+        "P2" => {
+            let mut values = Vec::with_capacity(count);
+            for _ in 0..count {
+                values.push(number(token()?)? as Float / max_value as Float);
+            }
+            values
+        }
+        "P5" => {
+            // a single whitespace byte separates the header from the raster
+            let start = pos + 1;
+            let bytes_per_value = if max_value > 255 { 2 } else { 1 };
+            let raster = data.get(start..start + count * bytes_per_value)
+                .ok_or_else(|| invalid("truncated pgm raster"))?;
+            raster
+                .chunks(bytes_per_value)
+                .map(|c| {
+                    let v = if bytes_per_value == 2 { ((c[0] as usize) << 8) | c[1] as usize } else { c[0] as usize };
+                    v as Float / max_value as Float
+                })
+                .collect()
+        }
+        _ => return Err(invalid("only P2 and P5 pgm files are supported")),
+    };
+
+    Ok(GrayImage { width, height, values })
+}
+
+// binary ppm (P6) from 8 bit rgb
+pub fn write_ppm(path: &Path, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    assert_eq!(rgb.len(), width * height * 3);
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    out.write_all(rgb)?;
+    out.flush()
+}
@@ -0,0 +1,50 @@
+use super::float::Float;
+use super::netpbm::GrayImage;
+
+// lattice hash mapped to [0, 1)
+fn hash(x: i64, y: i64, seed: u64) -> Float {
+    let mut h = seed ^ (x as u64).wrapping_mul(0x9e3779b97f4a7c15) ^ (y as u64).wrapping_mul(0xc2b2ae3d27d4eb4f);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^= h >> 33;
+    (h >> 11) as Float / (1u64 << 53) as Float
+}
+
+// smoothly interpolated random values on the integer lattice
+pub fn value_noise(x: Float, y: Float, seed: u64) -> Float {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (sx, sy) = (fx * fx * (3.0 - 2.0 * fx), fy * fy * (3.0 - 2.0 * fy));
+    let (ix, iy) = (x0 as i64, y0 as i64);
+
+    let top = hash(ix, iy, seed) * (1.0 - sx) + hash(ix + 1, iy, seed) * sx;
+    let bottom = hash(ix, iy + 1, seed) * (1.0 - sx) + hash(ix + 1, iy + 1, seed) * sx;
+    top * (1.0 - sy) + bottom * sy
+}
+
+// octaves of value noise, each twice the frequency and half the weight,
+// normalized back to [0, 1]
+pub fn fbm(x: Float, y: Float, octaves: u32, seed: u64) -> Float {
+    let (mut sum, mut weight, mut total, mut frequency) = (0.0, 1.0, 0.0, 1.0);
+    for octave in 0..octaves {
+        sum += weight * value_noise(x * frequency, y * frequency, seed.wrapping_add(octave as u64));
+        total += weight;
+        weight *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+// fbm sampled on a grid, features is how many base noise cells span the image
+pub fn noise_image(width: usize, height: usize, features: Float, seed: u64) -> GrayImage {
+    let scale = features / width.max(height) as Float;
+    let mut values = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            values.push(fbm(x as Float * scale, y as Float * scale, 6, seed));
+        }
+    }
+    GrayImage { width, height, values }
+}
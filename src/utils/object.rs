@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use super::aabb::AABB;
+use super::float::Float;
+use super::hit::Hit;
+use super::ray::Ray;
+use super::scatter::Scatter;
+use super::vec3::Vec3;
+
+// anything the tree can hold, it has to know its bounds and how it
+// moves from one frame to the next
+pub trait Object : Hit {
+    fn bounding_box(&self) -> AABB;
+
+    fn step_frame(&self, time_delta: Float) -> Box<dyn Object>;
+
+    // material used to identify the object in the material id aov
+    fn material(&self) -> Arc<dyn Scatter>;
+
+    fn movement(&self) -> Vec3;
+
+    fn center(&self) -> Vec3 {
+        let aabb = self.bounding_box();
+        (aabb.close_corner + aabb.far_corner) * 0.5
+    }
+
+    // distance from a point to the surface, zero inside. the default
+    // measures to the bounding box so it never overestimates
+    fn distance(&self, p: Vec3) -> Float {
+        self.bounding_box().distance_squared(p).sqrt()
+    }
+
+    // first t in [0, t_max] at which a sphere of the radius moving along
+    // the ray touches the object. the default sweeps against the bounding
+    // box so it may report contact early, never late
+    fn sphere_cast(&self, r: &Ray, radius: Float, t_max: Float) -> Option<Float> {
+        self.bounding_box().expanded(radius).clip(r, 0.0, t_max).map(|(t, _)| t)
+    }
+}
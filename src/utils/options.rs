@@ -0,0 +1,330 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use gif::{DisposalMethod, Repeat};
+
+use super::camera_model::Projection;
+use super::camera_path::{Easing, Interpolation};
+use super::debug_view::Heatmap;
+use super::filter::Filter;
+use super::float::Float;
+use super::gif_output::{Dither, GifSettings, PaletteMode, Quantizer};
+use super::lens::{ApertureShape, BokehMask, Lens};
+use super::netpbm::{read_pgm, GrayImage};
+use super::noise::noise_image;
+use super::sequence::ImageFormat;
+
+// render settings that can be changed from the command line
+pub struct Options {
+    pub aov_dir: Option<PathBuf>,
+    pub samples_per_pixel: u64,
+    pub denoise: bool,
+    pub filter: Filter,
+    pub projection: Projection,
+    pub camera_path: Option<Interpolation>,
+    pub easing: Easing,
+    pub track: Option<usize>,
+    pub aperture: Float,
+    pub lens: Lens,
+    pub autofocus: Option<(Float, Float)>,
+    pub gif: GifSettings,
+    pub sequence_dir: Option<PathBuf>,
+    pub sequence_format: ImageFormat,
+    pub y4m: Option<PathBuf>,
+    pub frames: Range<usize>,
+    pub spectral: bool,
+    pub max_depth: u64,
+    pub rr_depth: u64,
+    // replaces the ground sphere with a heightfield
+    pub terrain: Option<GrayImage>,
+    // object count for the tree builder benchmark instead of rendering
+    pub bench: Option<usize>,
+    pub leaf_size: usize,
+    // debug views of the tree, the heatmap replaces shading
+    pub heatmap: Option<Heatmap>,
+    pub wireframe: Option<usize>,
+}
+
+impl Options {
+    pub fn default() -> Options {
+        Options {
+            aov_dir: None,
+            samples_per_pixel: 50,
+            denoise: false,
+            filter: Filter::default(),
+            projection: Projection::Perspective,
+            camera_path: None,
+            easing: Easing::None,
+            track: None,
+            aperture: 0.0,
+            lens: Lens::default(),
+            autofocus: None,
+            gif: GifSettings::default(),
+            sequence_dir: None,
+            sequence_format: ImageFormat::Png,
+            y4m: None,
+            frames: 0..10,
+            spectral: false,
+            max_depth: 20,
+            rr_depth: 5,
+            terrain: None,
+            bench: None,
+            leaf_size: 4,
+            heatmap: None,
+            wireframe: None,
+        }
+    }
+
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--aovs" => {
+                    let dir = args.next().ok_or("--aovs expects a directory")?;
+                    options.aov_dir = Some(PathBuf::from(dir));
+                }
+                "--spp" => {
+                    options.samples_per_pixel = parse_value(&arg, args.next())?;
+                    if options.samples_per_pixel == 0 {
+                        return Err("--spp must be at least 1".to_string());
+                    }
+                }
+                "--denoise" => options.denoise = true,
+                "--filter" => {
+                    let name = args.next().ok_or("--filter expects a name")?;
+                    options.filter.kind = Filter::from_name(&name)
+                        .ok_or(format!("unknown filter {}", name))?;
+                }
+                "--filter-radius" => {
+                    options.filter.radius = parse_value(&arg, args.next())?;
+                    if !options.filter.radius.is_finite() || options.filter.radius <= 0.0 {
+                        return Err("--filter-radius must be positive".to_string());
+                    }
+                }
+                "--projection" => {
+                    let name = args.next().ok_or("--projection expects a name")?;
+                    options.projection = Projection::from_name(&name)
+                        .ok_or(format!("unknown projection {}", name))?;
+                }
+                "--camera-path" => {
+                    let name = args.next().ok_or("--camera-path expects an interpolation")?;
+                    options.camera_path = Some(match name.as_str() {
+                        "linear" => Interpolation::Linear,
+                        "catmull-rom" => Interpolation::CatmullRom,
+                        _ => return Err(format!("unknown interpolation {}", name)),
+                    });
+                }
+                "--easing" => {
+                    let name = args.next().ok_or("--easing expects a curve")?;
+                    options.easing = match name.as_str() {
+                        "none" => Easing::None,
+                        "in" => Easing::In,
+                        "out" => Easing::Out,
+                        "in-out" => Easing::InOut,
+                        _ => return Err(format!("unknown easing {}", name)),
+                    };
+                }
+                "--track" => {
+                    options.track = Some(parse_value(&arg, args.next())?);
+                }
+                "--aperture" => {
+                    options.aperture = parse_value(&arg, args.next())?;
+                    if !options.aperture.is_finite() || options.aperture < 0.0 {
+                        return Err("--aperture must be zero or positive".to_string());
+                    }
+                }
+                "--blades" => {
+                    let blades: u32 = parse_value(&arg, args.next())?;
+                    if blades < 3 {
+                        return Err("--blades needs at least 3 blades".to_string());
+                    }
+                    let rotation = match options.lens.shape {
+                        ApertureShape::Polygon { rotation, .. } => rotation,
+                        _ => 0.0,
+                    };
+                    options.lens.shape = ApertureShape::Polygon { blades, rotation };
+                }
+                "--blade-rotation" => {
+                    let rotation = parse_value(&arg, args.next())?;
+                    match &mut options.lens.shape {
+                        ApertureShape::Polygon { rotation: r, .. } => *r = rotation,
+                        _ => return Err("--blade-rotation needs --blades first".to_string()),
+                    }
+                }
+                "--bokeh" => {
+                    let path = args.next().ok_or("--bokeh expects a pgm file")?;
+                    let image = read_pgm(Path::new(&path)).map_err(|e| format!("{}: {}", path, e))?;
+                    let mask = BokehMask::new(&image).ok_or(format!("{} is completely black", path))?;
+                    options.lens.shape = ApertureShape::Image(Arc::new(mask));
+                }
+                "--cats-eye" => {
+                    options.lens.cats_eye = parse_value(&arg, args.next())?;
+                    // past 1 the stop shifts so far that it blacks out whole corners
+                    if !(0.0..=1.0).contains(&options.lens.cats_eye) {
+                        return Err("--cats-eye must be between 0 and 1".to_string());
+                    }
+                }
+                "--autofocus" => {
+                    let value = args.next().ok_or("--autofocus expects a pixel x,y")?;
+                    let (x, y) = value.split_once(',').ok_or("--autofocus expects a pixel x,y")?;
+                    let x = parse_value("--autofocus", Some(x.to_string()))?;
+                    let y = parse_value("--autofocus", Some(y.to_string()))?;
+                    options.autofocus = Some((x, y));
+                }
+                "--palette" => {
+                    let name = args.next().ok_or("--palette expects a mode")?;
+                    options.gif.palette = match name.as_str() {
+                        "per-frame" => PaletteMode::PerFrame,
+                        "global" => PaletteMode::Global,
+                        "stable" => PaletteMode::Stable,
+                        _ => return Err(format!("unknown palette mode {}", name)),
+                    };
+                }
+                "--quantizer" => {
+                    let name = args.next().ok_or("--quantizer expects a name")?;
+                    options.gif.quantizer = match name.as_str() {
+                        "median-cut" => Quantizer::MedianCut,
+                        "neuquant" => Quantizer::NeuQuant,
+                        _ => return Err(format!("unknown quantizer {}", name)),
+                    };
+                }
+                "--dither" => {
+                    let name = args.next().ok_or("--dither expects a name")?;
+                    options.gif.dither = match name.as_str() {
+                        "none" => Dither::None,
+                        "floyd-steinberg" => Dither::FloydSteinberg,
+                        "ordered" => Dither::Ordered,
+                        _ => return Err(format!("unknown dither {}", name)),
+                    };
+                }
+                "--fps" => {
+                    options.gif.fps = parse_value(&arg, args.next())?;
+                    if !options.gif.fps.is_finite() || options.gif.fps <= 0.0 {
+                        return Err("--fps must be positive".to_string());
+                    }
+                }
+                "--loops" => {
+                    // 0 loops forever like the gif extension itself
+                    let loops: u16 = parse_value(&arg, args.next())?;
+                    options.gif.repeat = if loops == 0 { Repeat::Infinite } else { Repeat::Finite(loops) };
+                }
+                "--disposal" => {
+                    let name = args.next().ok_or("--disposal expects a method")?;
+                    options.gif.disposal = match name.as_str() {
+                        "any" => DisposalMethod::Any,
+                        "keep" => DisposalMethod::Keep,
+                        "background" => DisposalMethod::Background,
+                        "previous" => DisposalMethod::Previous,
+                        _ => return Err(format!("unknown disposal {}", name)),
+                    };
+                }
+                "--sequence" => {
+                    let dir = args.next().ok_or("--sequence expects a directory")?;
+                    options.sequence_dir = Some(PathBuf::from(dir));
+                }
+                "--format" => {
+                    let name = args.next().ok_or("--format expects png, exr or ppm")?;
+                    options.sequence_format = ImageFormat::from_name(&name)
+                        .ok_or(format!("unknown image format {}", name))?;
+                }
+                "--y4m" => {
+                    let file = args.next().ok_or("--y4m expects a file")?;
+                    options.y4m = Some(PathBuf::from(file));
+                }
+                "--frames" => {
+                    // half open like a rust range, a single number renders one frame
+                    let value = args.next().ok_or("--frames expects a range like 20..40")?;
+                    options.frames = match value.split_once("..") {
+                        Some((start, end)) => {
+                            parse_value(&arg, Some(start.to_string()))?..parse_value(&arg, Some(end.to_string()))?
+                        }
+                        None => {
+                            let frame: usize = parse_value(&arg, Some(value))?;
+                            frame..frame + 1
+                        }
+                    };
+                    if options.frames.is_empty() {
+                        return Err("--frames range is empty".to_string());
+                    }
+                }
+                "--spectral" => options.spectral = true,
+                "--max-depth" => {
+                    options.max_depth = parse_value(&arg, args.next())?;
+                }
+                "--rr-depth" => {
+                    options.rr_depth = parse_value(&arg, args.next())?;
+                }
+                "--terrain" => {
+                    let source = args.next().ok_or("--terrain expects noise or a pgm file")?;
+                    let image = if source == "noise" {
+                        noise_image(257, 257, 6.0, 7)
+                    } else {
+                        read_pgm(Path::new(&source)).map_err(|e| format!("{}: {}", source, e))?
+                    };
+                    // the heightfield needs at least one cell
+                    if image.width < 2 || image.height < 2 {
+                        return Err(format!("{} is {}x{}, --terrain needs at least 2x2 pixels", source, image.width, image.height));
+                    }
+                    options.terrain = Some(image);
+                }
+                "--bench" => {
+                    options.bench = Some(parse_value(&arg, args.next())?);
+                }
+                "--leaf-size" => {
+                    options.leaf_size = parse_value(&arg, args.next())?;
+                    if options.leaf_size == 0 {
+                        return Err("--leaf-size must be at least 1".to_string());
+                    }
+                }
+                "--heatmap" => {
+                    let name = args.next().ok_or("--heatmap expects boxes or primitives")?;
+                    options.heatmap = Some(Heatmap::from_name(&name)
+                        .ok_or(format!("unknown heatmap {}", name))?);
+                }
+                "--wireframe" => {
+                    options.wireframe = Some(parse_value(&arg, args.next())?);
+                }
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} expects a value", flag))?;
+    value.parse().map_err(|_| format!("invalid value {} for {}", value, flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::from_args(["render"].iter().chain(args).map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        for value in ["0", "-1", "nan", "inf"] {
+            assert!(parse(&["--filter-radius", value]).is_err(), "--filter-radius {}", value);
+        }
+        assert_eq!(parse(&["--filter-radius", "1.5"]).unwrap().filter.radius, 1.5);
+        for value in ["-0.1", "nan", "inf"] {
+            assert!(parse(&["--aperture", value]).is_err(), "--aperture {}", value);
+        }
+        assert_eq!(parse(&["--aperture", "0"]).unwrap().aperture, 0.0);
+        for value in ["-0.1", "1.5", "nan"] {
+            assert!(parse(&["--cats-eye", value]).is_err(), "--cats-eye {}", value);
+        }
+        assert_eq!(parse(&["--cats-eye", "0.5"]).unwrap().lens.cats_eye, 0.5);
+        for value in ["0", "-5", "nan", "inf"] {
+            assert!(parse(&["--fps", value]).is_err(), "--fps {}", value);
+        }
+        assert_eq!(parse(&["--fps", "24"]).unwrap().gif.fps, 24.0);
+    }
+}
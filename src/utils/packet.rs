@@ -0,0 +1,113 @@
+use super::float::Float;
+use super::ray::Ray;
+
+// camera rays are traced in square tiles of this many pixels a side
+pub const PACKET_WIDTH: usize = 8;
+
+// interval bounds over the origins and inverse directions of a packet of
+// rays, a box no ray in the packet can enter is rejected with one test
+pub struct Frustum {
+    origin: [(Float, Float); 3],
+    inv: [(Float, Float); 3],
+    // direction sign per axis, shared by every ray
+    negative: [bool; 3],
+}
+
+impl Frustum {
+    // None when the rays point to both sides along some axis, or along a
+    // plane, and no interval over them stays finite
+    pub fn new(rays: &[Ray]) -> Option<Frustum> {
+        let first = rays.first()?;
+        let negative = first.inv_direction().values.map(|i| i < 0.0);
+        let mut origin = first.origin().values.map(|o| (o, o));
+        let mut inv = first.inv_direction().values.map(|i| (i, i));
+        for r in rays {
+            for axis in 0..3 {
+                let o = r.origin().values[axis];
+                let i = r.inv_direction().values[axis];
+                if !i.is_finite() || (i < 0.0) != negative[axis] {
+                    return None;
+                }
+                origin[axis] = (origin[axis].0.min(o), origin[axis].1.max(o));
+                inv[axis] = (inv[axis].0.min(i), inv[axis].1.max(i));
+            }
+        }
+        Some(Frustum { origin, inv, negative })
+    }
+
+    // lower bound on where any ray of the packet enters the box, None when
+    // none of them can hit it between t_min and t_max
+    pub fn enter(&self, lower: [Float; 3], upper: [Float; 3], t_min: Float, t_max: Float) -> Option<Float> {
+        let (mut t0, mut t1) = (t_min, t_max);
+        for axis in 0..3 {
+            let (near, far) = if self.negative[axis] {
+                (upper[axis], lower[axis])
+            } else {
+                (lower[axis], upper[axis])
+            };
+            t0 = t0.max(self.range(axis, near).0);
+            t1 = t1.min(self.range(axis, far).1);
+        }
+        if t0 <= t1 { Some(t0) } else { None }
+    }
+
+    // range of distances at which the rays cross the plane at x along axis
+    fn range(&self, axis: usize, x: Float) -> (Float, Float) {
+        let (o_lo, o_hi) = self.origin[axis];
+        let (i_lo, i_hi) = self.inv[axis];
+        let products = [(x - o_hi) * i_lo, (x - o_hi) * i_hi, (x - o_lo) * i_lo, (x - o_lo) * i_hi];
+        products.iter().fold((Float::INFINITY, Float::NEG_INFINITY), |(lo, hi), &p| (lo.min(p), hi.max(p)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::Frustum;
+    use crate::utils::aabb::AABB;
+    use crate::utils::float::Float;
+    use crate::utils::ray::Ray;
+    use crate::utils::vec3::Vec3;
+
+    fn random_vec(rng: &mut StdRng, lo: Float, hi: Float) -> Vec3 {
+        Vec3::new(rng.gen_range(lo..hi), rng.gen_range(lo..hi), rng.gen_range(lo..hi))
+    }
+
+    // the frustum may let boxes through that no ray enters, but never
+    // reject one that some ray does enter, nor claim a later entry
+    #[test]
+    fn never_culls_a_box_a_ray_enters() {
+        let mut rng = StdRng::seed_from_u64(14);
+        for _ in 0..200 {
+            // spread out origins like a lens, all heading the same octant
+            let center = random_vec(&mut rng, -5.0, 5.0);
+            let heading = random_vec(&mut rng, 0.2, 1.0);
+            let rays: Vec<Ray> = (0..16)
+                .map(|_| Ray::new(center + random_vec(&mut rng, -0.5, 0.5), heading + random_vec(&mut rng, -0.15, 0.15)))
+                .collect();
+            let frustum = Frustum::new(&rays).unwrap();
+
+            for _ in 0..50 {
+                let corner = center + random_vec(&mut rng, -2.0, 12.0);
+                let aabb = AABB::new(corner, corner + random_vec(&mut rng, 0.1, 3.0));
+                let entry = rays
+                    .iter()
+                    .filter_map(|r| aabb.clip(r, 0.0, 20.0))
+                    .map(|(t0, _)| t0)
+                    .fold(None, |best: Option<Float>, t| Some(best.map_or(t, |b| b.min(t))));
+                let culled = frustum.enter(aabb.close_corner.values, aabb.far_corner.values, 0.0, 20.0);
+                if let Some(t) = entry {
+                    assert!(culled.is_some_and(|c| c <= t + 1e-4), "{:?} for a ray entering at {}", culled, t);
+                }
+            }
+        }
+
+        // a direction that flips sign within the packet, or lies in a plane
+        let flipped = [Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.1, 1.0, 1.0)), Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(-0.1, 1.0, 1.0))];
+        assert!(Frustum::new(&flipped).is_none());
+        assert!(Frustum::new(&[Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 1.0))]).is_none());
+        assert!(Frustum::new(&[]).is_none());
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// largest payload of a stored (uncompressed) deflate block
+const MAX_STORED_BLOCK: usize = 65535;
+
+// writes 8 bit rgb as a png, the image data is stored uncompressed so
+// no deflate implementation is needed
+pub fn write_png(path: &Path, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    assert_eq!(rgb.len(), width * height * 3);
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth 8, truecolor, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut out, b"IHDR", &header)?;
+
+    // every scanline starts with its filter type, 0 means none
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in rgb.chunks(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut out, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(&mut out, b"IEND", &[])?;
+    out.flush()
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    out.write_all(&crc.finish().to_be_bytes())
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_STORED_BLOCK * 5 + 11);
+    // deflate with a 32k window and no preset dictionary
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(MAX_STORED_BLOCK).collect() };
+    for (i, block) in blocks.iter().enumerate() {
+        let last = i + 1 == blocks.len();
+        out.push(last as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+struct Crc32 {
+    table: [u32; 256],
+    value: u32,
+}
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        let mut table = [0u32; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+        Crc32 { table, value: 0xffffffff }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.value = self.table[((self.value ^ byte as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.value ^ 0xffffffff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, write_png, zlib_stored, Crc32, MAX_STORED_BLOCK};
+
+    // check values from the crc and zlib specs
+    #[test]
+    fn checksums_match_known_answers() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+        assert_eq!(Crc32::new().finish(), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(&[]), 1);
+        // long enough to need the deferred modulo
+        assert_eq!(adler32(&[0xff; 100_000]), 0x149a302c);
+    }
+
+    #[test]
+    fn stored_blocks_are_framed() {
+        assert_eq!(zlib_stored(&[]), [0x78, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]);
+        assert_eq!(
+            zlib_stored(b"abc"),
+            [0x78, 0x01, 1, 3, 0, 0xfc, 0xff, b'a', b'b', b'c', 0x02, 0x4d, 0x01, 0x27]
+        );
+
+        // data over the block limit is split, only the final block is marked last
+        let data: Vec<u8> = (0..MAX_STORED_BLOCK + 10).map(|i| i as u8).collect();
+        let out = zlib_stored(&data);
+        assert_eq!(out.len(), 2 + 5 + MAX_STORED_BLOCK + 5 + 10 + 4);
+        assert_eq!(out[2..7], [0, 0xff, 0xff, 0, 0]);
+        let second = 7 + MAX_STORED_BLOCK;
+        assert_eq!(out[second..second + 5], [1, 10, 0, 0xf5, 0xff]);
+        assert_eq!(out[second + 5..second + 15], data[MAX_STORED_BLOCK..]);
+        assert_eq!(out[out.len() - 4..], adler32(&data).to_be_bytes());
+    }
+
+    #[test]
+    fn png_chunks_carry_their_crc() {
+        let path = std::env::temp_dir().join(format!("png-test-{}.png", std::process::id()));
+        write_png(&path, 2, 1, &[255, 0, 0, 0, 0, 255]).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes[..8], [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']);
+        let mut pos = 8;
+        let mut kinds = Vec::new();
+        while pos < bytes.len() {
+            let len = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+            let body = &bytes[pos + 4..pos + 8 + len];
+            let mut crc = Crc32::new();
+            crc.update(body);
+            assert_eq!(bytes[pos + 8 + len..pos + 12 + len], crc.finish().to_be_bytes());
+            kinds.push(String::from_utf8(body[..4].to_vec()).unwrap());
+            if &body[..4] == b"IDAT" {
+                // one filter byte in front of the scanline
+                assert_eq!(body[4..], zlib_stored(&[0, 255, 0, 0, 0, 0, 255]));
+            }
+            pos += 12 + len;
+        }
+        assert_eq!(kinds, ["IHDR", "IDAT", "IEND"]);
+    }
+}
@@ -0,0 +1,308 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use super::aabb::{Tree, AABB};
+use super::float::Float;
+use super::ray::Ray;
+use super::vec3::Vec3;
+
+// convex volume bounded by planes, a point p is inside a plane (n, d) when
+// n.p + d >= 0
+pub struct ViewFrustum {
+    planes: Vec<(Vec3, Float)>,
+}
+
+impl ViewFrustum {
+    pub fn new(planes: Vec<(Vec3, Float)>) -> ViewFrustum {
+        ViewFrustum { planes }
+    }
+
+    // what a perspective camera sees between the near and far distance,
+    // vfov in degrees like the camera keyframes
+    pub fn perspective(lookfrom: Vec3, lookat: Vec3, vup: Vec3, vfov: Float, aspect_ratio: Float, near: Float, far: Float) -> ViewFrustum {
+        let forward = (lookat - lookfrom).unit_vector();
+        let right = forward.cross(vup).unit_vector();
+        let up = right.cross(forward);
+        let half_height = (vfov.to_radians() / 2.0).tan();
+        let half_width = half_height * aspect_ratio;
+
+        let plane = |n: Vec3| {
+            let n = n.unit_vector();
+            (n, -n.dot(lookfrom))
+        };
+        ViewFrustum::new(vec![
+            (forward, -forward.dot(lookfrom + forward * near)),
+            (forward * -1.0, forward.dot(lookfrom + forward * far)),
+            // side planes hold the eye and point inwards
+            plane(up.cross(forward + right * half_width)),
+            plane((forward - right * half_width).cross(up)),
+            plane((forward + up * half_height).cross(right)),
+            plane(right.cross(forward - up * half_height)),
+        ])
+    }
+
+    pub fn contains(&self, p: Vec3) -> bool {
+        self.planes.iter().all(|(n, d)| n.dot(p) + d >= 0.0)
+    }
+
+    // conservative, boxes near the corners can pass without being inside
+    pub fn intersects(&self, aabb: &AABB) -> bool {
+        self.planes.iter().all(|(n, d)| {
+            // the corner furthest along the normal
+            let p = Vec3::new(
+                if n.x() >= 0.0 { aabb.far_corner.x() } else { aabb.close_corner.x() },
+                if n.y() >= 0.0 { aabb.far_corner.y() } else { aabb.close_corner.y() },
+                if n.z() >= 0.0 { aabb.far_corner.z() } else { aabb.close_corner.z() },
+            );
+            n.dot(p) + d >= 0.0
+        })
+    }
+}
+
+// heap entry ordered so the smallest distance pops first
+struct Candidate {
+    distance: Float,
+    node: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+// queries other than rays, all of them return object indices and prune
+// with the node boxes
+impl Tree {
+    // objects whose boxes pass keep_object, descending only into nodes
+    // whose boxes pass keep_node
+    fn collect(&self, keep_node: impl Fn(&AABB) -> bool, keep_object: impl Fn(usize) -> bool) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack: Vec<usize> = self.root().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            if !keep_node(self.node_aabb(node)) {
+                continue;
+            }
+            match self.children(node) {
+                Some((left, right)) => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+                None => found.extend(self.items(node).iter().cloned().filter(|&i| keep_object(i))),
+            }
+        }
+        found
+    }
+
+    // objects whose bounding boxes overlap the box
+    pub fn query_aabb(&self, aabb: &AABB) -> Vec<usize> {
+        self.collect(|node| node.intersects(aabb), |i| self.object(i).bounding_box().intersects(aabb))
+    }
+
+    // objects with some part within radius of the point
+    pub fn query_radius(&self, center: Vec3, radius: Float) -> Vec<usize> {
+        let r2 = radius * radius;
+        self.collect(|node| node.distance_squared(center) <= r2, |i| self.object(i).distance(center) <= radius)
+    }
+
+    // objects whose bounding boxes are at least partly inside the frustum
+    pub fn query_frustum(&self, frustum: &ViewFrustum) -> Vec<usize> {
+        self.collect(|node| frustum.intersects(node), |i| frustum.intersects(&self.object(i).bounding_box()))
+    }
+
+    // the k objects closest to a point with their distances, nearest first
+    pub fn nearest(&self, point: Vec3, k: usize) -> Vec<(usize, Float)> {
+        let mut found: Vec<(usize, Float)> = Vec::with_capacity(k);
+        if k == 0 {
+            return found;
+        }
+
+        // best first over the nodes, stopping once the closest unvisited
+        // node is further away than the k-th object found so far
+        let mut heap = BinaryHeap::new();
+        if let Some(root) = self.root() {
+            heap.push(Candidate { distance: self.node_aabb(root).distance_squared(point).sqrt(), node: root });
+        }
+        while let Some(Candidate { distance, node }) = heap.pop() {
+            if found.len() == k && distance > found[k - 1].1 {
+                break;
+            }
+            match self.children(node) {
+                Some((left, right)) => {
+                    for child in [left, right] {
+                        let distance = self.node_aabb(child).distance_squared(point).sqrt();
+                        heap.push(Candidate { distance, node: child });
+                    }
+                }
+                None => {
+                    for &i in self.items(node) {
+                        let distance = self.object(i).distance(point);
+                        if found.len() < k || distance < found[k - 1].1 {
+                            let at = found.partition_point(|&(_, d)| d <= distance);
+                            found.insert(at, (i, distance));
+                            found.truncate(k);
+                        }
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    // sweeps a sphere from start along motion and returns the first object
+    // it touches with the fraction of the motion covered, in [0, 1]
+    pub fn sphere_cast(&self, start: Vec3, radius: Float, motion: Vec3) -> Option<(usize, Float)> {
+        let r = Ray::new(start, motion);
+        let mut best: Option<(usize, Float)> = None;
+        let mut stack: Vec<usize> = self.root().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let closest = best.map_or(1.0, |(_, t)| t);
+            if self.node_aabb(node).expanded(radius).clip(&r, 0.0, closest).is_none() {
+                continue;
+            }
+            match self.children(node) {
+                Some((left, right)) => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+                None => {
+                    for &i in self.items(node) {
+                        let closest = best.map_or(1.0, |(_, t)| t);
+                        if let Some(t) = self.object(i).sphere_cast(&r, radius, closest) {
+                            if best.is_none_or(|(_, b)| t < b) {
+                                best = Some((i, t));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::ViewFrustum;
+    use crate::utils::aabb::{Tree, AABB};
+    use crate::utils::color::Color;
+    use crate::utils::float::Float;
+    use crate::utils::lambertian::Lambertian;
+    use crate::utils::object::Object;
+    use crate::utils::ray::Ray;
+    use crate::utils::sphere::Sphere;
+    use crate::utils::vec3::Vec3;
+
+    fn random_point(rng: &mut StdRng, extent: Float) -> Vec3 {
+        Vec3::new(rng.gen_range(-extent..extent), rng.gen_range(-extent..extent), rng.gen_range(-extent..extent))
+    }
+
+    fn scene(seed: u64) -> Tree {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let objects: Vec<Box<dyn Object>> = (0..300)
+            .map(|_| {
+                let center = random_point(&mut rng, 20.0);
+                Box::new(Sphere::new(center, rng.gen_range(0.1..1.0), mat.clone(), Vec3::new(0.0, 0.0, 0.0))) as Box<dyn Object>
+            })
+            .collect();
+        Tree::from(objects)
+    }
+
+    fn sorted(mut v: Vec<usize>) -> Vec<usize> {
+        v.sort_unstable();
+        v
+    }
+
+    // every query has to agree with checking each object on its own
+    #[test]
+    fn queries_match_brute_force() {
+        let tree = scene(3);
+        let all = 0..tree.len();
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..50 {
+            let a = random_point(&mut rng, 20.0);
+            let b = random_point(&mut rng, 20.0);
+            let aabb = AABB::new(Vec3::min(a, b), Vec3::max(a, b));
+            let expected: Vec<usize> = all.clone().filter(|&i| tree.object(i).bounding_box().intersects(&aabb)).collect();
+            assert_eq!(sorted(tree.query_aabb(&aabb)), expected);
+
+            let radius = rng.gen_range(0.0..8.0);
+            let expected: Vec<usize> = all.clone().filter(|&i| tree.object(i).distance(a) <= radius).collect();
+            assert_eq!(sorted(tree.query_radius(a, radius)), expected);
+
+            let k = rng.gen_range(1..10);
+            let mut expected: Vec<Float> = all.clone().map(|i| tree.object(i).distance(a)).collect();
+            expected.sort_by(Float::total_cmp);
+            let found: Vec<Float> = tree.nearest(a, k).iter().map(|&(_, d)| d).collect();
+            assert_eq!(found, expected[..k].to_vec());
+
+            let radius = rng.gen_range(0.0..2.0);
+            let r = Ray::new(a, b - a);
+            let expected = all
+                .clone()
+                .filter_map(|i| tree.object(i).sphere_cast(&r, radius, 1.0).map(|t| (i, t)))
+                .min_by(|x, y| x.1.total_cmp(&y.1));
+            assert_eq!(tree.sphere_cast(a, radius, b - a), expected);
+        }
+    }
+
+    #[test]
+    fn frustum_query() {
+        let tree = scene(5);
+        let frustum = ViewFrustum::perspective(
+            Vec3::new(30.0, 5.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.5,
+            0.1,
+            100.0,
+        );
+        assert!(frustum.contains(Vec3::new(0.0, 0.0, 0.0)));
+        assert!(!frustum.contains(Vec3::new(40.0, 5.0, 0.0)));
+        assert!(!frustum.contains(Vec3::new(0.0, 0.0, 40.0)));
+
+        let found = sorted(tree.query_frustum(&frustum));
+        let expected: Vec<usize> = (0..tree.len()).filter(|&i| frustum.intersects(&tree.object(i).bounding_box())).collect();
+        assert_eq!(found, expected);
+        // everything whose center is visible has to be found
+        for i in 0..tree.len() {
+            if frustum.contains(tree.center(i)) {
+                assert!(found.contains(&i));
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_cast_touches_at_the_right_time() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let target = Sphere::new(Vec3::new(10.0, 0.0, 0.0), 1.0, mat, Vec3::new(0.0, 0.0, 0.0));
+        let tree = Tree::from(vec![Box::new(target) as Box<dyn Object>]);
+        // moving 20 units, contact when the centers are 1.5 apart
+        let (idx, t) = tree.sphere_cast(Vec3::new(0.0, 0.0, 0.0), 0.5, Vec3::new(20.0, 0.0, 0.0)).unwrap();
+        assert_eq!(idx, 0);
+        assert!((t - 8.5 / 20.0).abs() < 1e-12);
+        assert!(tree.sphere_cast(Vec3::new(0.0, 2.0, 0.0), 0.5, Vec3::new(20.0, 0.0, 0.0)).is_none());
+        assert!(tree.sphere_cast(Vec3::new(0.0, 0.0, 0.0), 0.5, Vec3::new(5.0, 0.0, 0.0)).is_none());
+    }
+}
@@ -1,9 +1,12 @@
+use super::float::Float;
 use super::vec3::Vec3;
 
 #[derive(Copy, Clone)]
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
+    // reciprocal of the direction, box tests multiply instead of divide
+    inv_direction: Vec3,
 }
 
 impl Ray {
@@ -11,6 +14,7 @@ impl Ray {
         Ray {
             origin: o,
             direction: dir,
+            inv_direction: Vec3::new(1.0 / dir.x(), 1.0 / dir.y(), 1.0 / dir.z()),
         }
     }
     pub fn origin(self) -> Vec3 {
@@ -19,7 +23,10 @@ impl Ray {
     pub fn direction(self) -> Vec3 {
         self.direction
     }
-    pub fn at(self, t: f64) -> Vec3 {
-        return self.origin + (self.direction * t);
+    pub fn inv_direction(self) -> Vec3 {
+        self.inv_direction
+    }
+    pub fn at(self, t: Float) -> Vec3 {
+        self.origin + (self.direction * t)
     }
 }
@@ -0,0 +1,197 @@
+use super::float::Float;
+
+// real roots of a x^2 + b x + c in increasing order, in the form that
+// avoids cancellation when b is large
+pub fn solve_quadratic(a: Float, b: Float, c: Float) -> Option<(Float, Float)> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        return Some((-c / b, -c / b));
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+// largest real root of x^3 + a x^2 + b x + c
+fn largest_cubic_root(a: Float, b: Float, c: Float) -> Float {
+    // depressed form z^3 + p z + q with x = z - a / 3
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a.powi(3) / 27.0 - a * b / 3.0 + c;
+    let discriminant = (q / 2.0).powi(2) + (p / 3.0).powi(3);
+    let z = if discriminant > 0.0 {
+        let s = discriminant.sqrt();
+        (-q / 2.0 + s).cbrt() + (-q / 2.0 - s).cbrt()
+    } else if p == 0.0 {
+        // triple root, q is zero as well
+        0.0
+    } else {
+        let r = (-p / 3.0).sqrt();
+        let phi = (-q / (2.0 * r.powi(3))).clamp(-1.0, 1.0).acos();
+        2.0 * r * (phi / 3.0).cos()
+    };
+    let mut x = z - a / 3.0;
+    // polish, the closed form loses digits when roots are close
+    for _ in 0..2 {
+        let f = ((x + a) * x + b) * x + c;
+        let df = (3.0 * x + 2.0 * a) * x + b;
+        if df != 0.0 {
+            x -= f / df;
+        }
+    }
+    x
+}
+
+// real roots of x^4 + a x^3 + b x^2 + c x + d with ferrari's method,
+// unsorted and refined with newton steps on the original polynomial
+pub fn solve_quartic(a: Float, b: Float, c: Float, d: Float) -> Vec<Float> {
+    // depressed form y^4 + p y^2 + q y + r with x = y - a / 4
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut ys = Vec::with_capacity(4);
+    if q.abs() < 1e-12 {
+        // biquadratic
+        if let Some((z0, z1)) = solve_quadratic(1.0, p, r) {
+            for z in [z0, z1] {
+                if z >= 0.0 {
+                    ys.push(z.sqrt());
+                    ys.push(-z.sqrt());
+                }
+            }
+        }
+    } else {
+        // m > 0 makes the remainder a perfect square, splitting the
+        // quartic into two quadratics
+        let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+        let s = (2.0 * m).sqrt();
+        for sign in [1.0, -1.0] {
+            let c0 = p / 2.0 + m + sign * s * q / (4.0 * m);
+            if let Some((y0, y1)) = solve_quadratic(1.0, -sign * s, c0) {
+                ys.push(y0);
+                ys.push(y1);
+            }
+        }
+    }
+
+    ys.into_iter()
+        .map(|y| {
+            let mut x = y - a / 4.0;
+            for _ in 0..2 {
+                let f = (((x + a) * x + b) * x + c) * x + d;
+                let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+                if df != 0.0 {
+                    x -= f / df;
+                }
+            }
+            x
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{largest_cubic_root, solve_quadratic, solve_quartic};
+    use crate::utils::float::Float;
+
+    // roots of the monic polynomial with the given roots, found roots are
+    // sorted and compared with a tolerance relative to their size
+    fn assert_roots(mut found: Vec<Float>, expected: &[Float], tolerance: Float) {
+        found.sort_by(Float::total_cmp);
+        assert_eq!(found.len(), expected.len(), "{:?} vs {:?}", found, expected);
+        for (x, e) in found.iter().zip(expected) {
+            assert!((x - e).abs() <= tolerance * e.abs().max(1.0), "{:?} vs {:?}", found, expected);
+        }
+    }
+
+    fn quartic_from_roots(r: [Float; 4]) -> Vec<Float> {
+        // expand (x - r0)(x - r1)(x - r2)(x - r3)
+        let mut c = vec![1.0];
+        for root in r {
+            let mut next = vec![0.0; c.len() + 1];
+            for (i, &k) in c.iter().enumerate() {
+                next[i] += k;
+                next[i + 1] -= k * root;
+            }
+            c = next;
+        }
+        solve_quartic(c[1], c[2], c[3], c[4])
+    }
+
+    #[test]
+    fn quadratic() {
+        let eps = Float::EPSILON * 4.0;
+        let (a, b) = solve_quadratic(1.0, -4.0, 3.0).unwrap();
+        assert_roots(vec![a, b], &[1.0, 3.0], eps);
+        assert!(a <= b);
+        let (a, b) = solve_quadratic(2.0, -8.0, 8.0).unwrap();
+        assert_roots(vec![a, b], &[2.0, 2.0], eps);
+        // linear and without real roots
+        assert_eq!(solve_quadratic(0.0, 2.0, -4.0), Some((2.0, 2.0)));
+        assert_eq!(solve_quadratic(0.0, 0.0, 1.0), None);
+        assert_eq!(solve_quadratic(1.0, 0.0, 1.0), None);
+        // widely separated roots, the textbook formula cancels the small
+        // one down to a few digits
+        let (a, b) = solve_quadratic(1.0, -(1e5 + 1e-3), 100.0).unwrap();
+        assert_roots(vec![a, b], &[1e-3, 1e5], eps);
+    }
+
+    #[test]
+    fn cubic() {
+        let eps = Float::EPSILON * 16.0;
+        // (x - 1)(x - 2)(x - 3)
+        assert_roots(vec![largest_cubic_root(-6.0, 11.0, -6.0)], &[3.0], eps);
+        // (x - 2)(x^2 + 1), a single real root
+        assert_roots(vec![largest_cubic_root(-2.0, 1.0, -2.0)], &[2.0], eps);
+        // (x + 1)(x - 1)^2, the double root is the largest
+        assert_roots(vec![largest_cubic_root(-1.0, -1.0, 1.0)], &[1.0], Float::EPSILON.sqrt() * 4.0);
+        // (x - 1)^3 only keeps about a third of the digits
+        assert_roots(vec![largest_cubic_root(-3.0, 3.0, -1.0)], &[1.0], Float::EPSILON.cbrt() * 4.0);
+    }
+
+    #[test]
+    fn quartic() {
+        let eps = Float::EPSILON * 64.0;
+        assert_roots(quartic_from_roots([1.0, 2.0, 3.0, 4.0]), &[1.0, 2.0, 3.0, 4.0], eps);
+        assert_roots(quartic_from_roots([-3.0, -0.5, 0.25, 7.0]), &[-3.0, -0.5, 0.25, 7.0], eps);
+        // biquadratic, no odd terms
+        assert_roots(solve_quartic(0.0, -5.0, 0.0, 4.0), &[-2.0, -1.0, 1.0, 2.0], eps);
+        // two real roots and a complex pair, (x^2 - 1)(x^2 + 1)
+        assert_roots(solve_quartic(0.0, 0.0, 0.0, -1.0), &[-1.0, 1.0], eps);
+        // no real roots
+        assert!(solve_quartic(0.0, 0.0, 0.0, 1.0).is_empty());
+        assert!(solve_quartic(0.0, 2.0, 0.0, 1.0).is_empty());
+        // double roots, what a ray grazing a torus produces, keep about
+        // half the digits
+        assert_roots(quartic_from_roots([1.0, 1.0, 3.0, 3.0]), &[1.0, 1.0, 3.0, 3.0], Float::EPSILON.sqrt() * 8.0);
+        // two nearly equal roots
+        let close = 2.0 + Float::EPSILON.sqrt();
+        assert_roots(quartic_from_roots([-1.0, 2.0, close, 5.0]), &[-1.0, 2.0, close, 5.0], Float::EPSILON.sqrt() * 8.0);
+    }
+
+    // a ray along x through a torus with R = 1 and r = 0.25. the roots lose
+    // digits as the origin moves away, from 10000 the tube is gone, so the
+    // torus solves from where the ray enters its bounds at x = -1.25
+    #[test]
+    fn quartic_far_origin() {
+        // the torus coefficients for an origin at x on the axis
+        let coefficients = |x: Float| {
+            let e = x * x - 1.0 - 0.0625;
+            [4.0 * x, 2.0 * e + 4.0 * x * x, 4.0 * x * e, e * e - 0.25]
+        };
+        let [a, b, c, d] = coefficients(-1.25);
+        assert_roots(solve_quartic(a, b, c, d), &[0.0, 0.5, 2.0, 2.5], Float::EPSILON * 64.0);
+        let [a, b, c, d] = coefficients(-10.0);
+        assert_roots(solve_quartic(a, b, c, d), &[8.75, 9.25, 10.75, 11.25], Float::EPSILON.sqrt());
+    }
+}
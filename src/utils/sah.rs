@@ -0,0 +1,164 @@
+use super::aabb::AABB;
+use super::float::Float;
+use super::vec3::Vec3;
+
+const BINS: usize = 16;
+// cost of visiting a node relative to testing one object
+pub const TRAVERSAL_COST: Float = 1.0;
+// below this many objects the recursion stays on the current thread
+const PARALLEL_THRESHOLD: usize = 4096;
+// past this depth splits fall back to halving, keeping the depth bounded
+// for traversals with fixed size stacks
+const MAX_SAH_DEPTH: usize = 64;
+
+// tree produced by the builder before it is laid out in the tree's arena
+pub enum BuildNode {
+    Leaf { aabb: AABB, items: Vec<usize> },
+    Interior { aabb: AABB, left: Box<BuildNode>, right: Box<BuildNode> },
+}
+
+fn bounds_of(boxes: &[AABB], items: &[usize]) -> AABB {
+    let mut close = boxes[items[0]].close_corner;
+    let mut far = boxes[items[0]].far_corner;
+    for &i in &items[1..] {
+        close = Vec3::min(close, boxes[i].close_corner);
+        far = Vec3::max(far, boxes[i].far_corner);
+    }
+    AABB::new(close, far)
+}
+
+fn centroid(aabb: &AABB) -> Vec3 {
+    (aabb.close_corner + aabb.far_corner) * 0.5
+}
+
+#[derive(Clone)]
+struct Bin {
+    count: usize,
+    close: Vec3,
+    far: Vec3,
+}
+
+impl Bin {
+    fn empty() -> Bin {
+        let inf = Float::INFINITY;
+        Bin { count: 0, close: Vec3::new(inf, inf, inf), far: Vec3::new(-inf, -inf, -inf) }
+    }
+
+    fn grow(&mut self, other: &Bin) {
+        self.count += other.count;
+        self.close = Vec3::min(self.close, other.close);
+        self.far = Vec3::max(self.far, other.far);
+    }
+
+    fn area(&self) -> Float {
+        if self.count == 0 { 0.0 } else { AABB::compute_surface_area(self.close, self.far) }
+    }
+}
+
+// plane between two bins along an axis of the centroid bounds
+struct Split {
+    // relative to testing every object in a leaf
+    cost: Float,
+    axis: usize,
+    boundary: usize,
+    min: Float,
+    extent: Float,
+}
+
+impl Split {
+    fn goes_left(&self, aabb: &AABB) -> bool {
+        bin_index(centroid(aabb).values[self.axis], self.min, self.extent) < self.boundary
+    }
+}
+
+fn best_split(boxes: &[AABB], items: &[usize], aabb: &AABB) -> Option<Split> {
+    let mut lower = centroid(&boxes[items[0]]);
+    let mut upper = lower;
+    for &i in items {
+        let c = centroid(&boxes[i]);
+        lower = Vec3::min(lower, c);
+        upper = Vec3::max(upper, c);
+    }
+
+    let mut best: Option<Split> = None;
+    for axis in 0..3 {
+        let (min, extent) = (lower.values[axis], upper.values[axis] - lower.values[axis]);
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut bins = vec![Bin::empty(); BINS];
+        for &i in items {
+            let b = bin_index(centroid(&boxes[i]).values[axis], min, extent);
+            bins[b].count += 1;
+            bins[b].close = Vec3::min(bins[b].close, boxes[i].close_corner);
+            bins[b].far = Vec3::max(bins[b].far, boxes[i].far_corner);
+        }
+
+        // sweep from the right to get the cost of everything past each boundary
+        let mut right = vec![Bin::empty(); BINS];
+        let mut acc = Bin::empty();
+        for b in (1..BINS).rev() {
+            acc.grow(&bins[b]);
+            right[b] = acc.clone();
+        }
+
+        let mut left = Bin::empty();
+        for b in 1..BINS {
+            left.grow(&bins[b - 1]);
+            let r = &right[b];
+            if left.count == 0 || r.count == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST
+                + (left.area() * left.count as Float + r.area() * r.count as Float) / aabb.surface_area.max(1e-12);
+            if best.as_ref().is_none_or(|s| cost < s.cost) {
+                best = Some(Split { cost, axis, boundary: b, min, extent });
+            }
+        }
+    }
+    best
+}
+
+fn bin_index(c: Float, min: Float, extent: Float) -> usize {
+    (((c - min) / extent * BINS as Float) as usize).min(BINS - 1)
+}
+
+fn build_items(boxes: &[AABB], mut items: Vec<usize>, leaf_size: usize, depth: usize) -> BuildNode {
+    let aabb = bounds_of(boxes, &items);
+    if items.len() == 1 {
+        return BuildNode::Leaf { aabb, items };
+    }
+
+    let split = if depth < MAX_SAH_DEPTH { best_split(boxes, &items, &aabb) } else { None };
+    let leaf_cost = items.len() as Float;
+    let (left, right): (Vec<usize>, Vec<usize>) = match split {
+        Some(s) if items.len() <= leaf_size && s.cost >= leaf_cost => {
+            return BuildNode::Leaf { aabb, items };
+        }
+        Some(s) => items.iter().partition(|&&i| s.goes_left(&boxes[i])),
+        // every centroid in the same spot or too deep, nothing to gain
+        // from splitting
+        None if items.len() <= leaf_size => return BuildNode::Leaf { aabb, items },
+        None => {
+            let right = items.split_off(items.len() / 2);
+            (items, right)
+        }
+    };
+
+    let (l, r) = if left.len() + right.len() > PARALLEL_THRESHOLD {
+        rayon::join(
+            || build_items(boxes, left, leaf_size, depth + 1),
+            || build_items(boxes, right, leaf_size, depth + 1),
+        )
+    } else {
+        (build_items(boxes, left, leaf_size, depth + 1), build_items(boxes, right, leaf_size, depth + 1))
+    };
+    BuildNode::Interior { aabb, left: Box::new(l), right: Box::new(r) }
+}
+
+// top down binned surface area heuristic build over the given boxes,
+// leaves hold at most leaf_size objects
+pub fn build(boxes: &[AABB], leaf_size: usize) -> BuildNode {
+    build_items(boxes, (0..boxes.len()).collect(), leaf_size.max(1), 0)
+}
@@ -1,7 +1,28 @@
 use super::color::Color;
+use super::float::Float;
 use super::hit_record::HitRecord;
+use super::medium::Medium;
 use super::ray::Ray;
+use super::spectrum::rgb_to_spectrum;
 
 pub trait Scatter : Send + Sync {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+
+    // single wavelength version for the spectral renderer, materials
+    // without spectral data upsample their rgb attenuation
+    fn scatter_spectral(&self, r_in: &Ray, rec: &HitRecord, lambda: Float) -> Option<(Float, Ray)> {
+        self.scatter(r_in, rec)
+            .map(|(attenuation, scattered)| (rgb_to_spectrum(attenuation, lambda), scattered))
+    }
+
+    // closed surfaces bounding a refractive volume describe its interior,
+    // the integrator then tracks which media a path is inside of
+    fn medium(&self) -> Option<Medium> {
+        None
+    }
+
+    // base color of the surface, used for the albedo aov
+    fn albedo(&self) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
 }
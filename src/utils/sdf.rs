@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use super::aabb::AABB;
+use super::float::Float;
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::object::Object;
+use super::ray::Ray;
+use super::scatter::Scatter;
+use super::vec3::Vec3;
+
+const MAX_STEPS: usize = 256;
+// surface threshold and finite difference step, in object space
+const EPSILON: Float = 1e-4;
+
+// signed distance to a surface, negative inside. implementations should
+// never overestimate the distance or the marcher can step through
+pub trait Sdf : Send + Sync {
+    fn distance(&self, p: Vec3) -> Float;
+}
+
+impl<F: Fn(Vec3) -> Float + Send + Sync> Sdf for F {
+    fn distance(&self, p: Vec3) -> Float {
+        self(p)
+    }
+}
+
+fn abs(p: Vec3) -> Vec3 {
+    Vec3::new(p.x().abs(), p.y().abs(), p.z().abs())
+}
+
+fn positive(p: Vec3) -> Vec3 {
+    Vec3::max(p, Vec3::new(0.0, 0.0, 0.0))
+}
+
+// torus around the y axis
+pub struct Torus {
+    pub major: Float,
+    pub minor: Float,
+}
+
+impl Sdf for Torus {
+    fn distance(&self, p: Vec3) -> Float {
+        let ring = (p.x().powi(2) + p.z().powi(2)).sqrt() - self.major;
+        (ring.powi(2) + p.y().powi(2)).sqrt() - self.minor
+    }
+}
+
+pub struct RoundedBox {
+    pub half_extents: Vec3,
+    pub radius: Float,
+}
+
+impl Sdf for RoundedBox {
+    fn distance(&self, p: Vec3) -> Float {
+        let r = self.radius;
+        let q = abs(p) - self.half_extents + Vec3::new(r, r, r);
+        positive(q).length() + q.x().max(q.y()).max(q.z()).min(0.0) - r
+    }
+}
+
+// segment from a to b swept by a sphere
+pub struct Capsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: Float,
+}
+
+impl Sdf for Capsule {
+    fn distance(&self, p: Vec3) -> Float {
+        let pa = p - self.a;
+        let ba = self.b - self.a;
+        let h = (pa.dot(ba) / ba.length_squared()).clamp(0.0, 1.0);
+        (pa - ba * h).length() - self.radius
+    }
+}
+
+// polynomial smooth minimum, k is roughly the width of the blend
+pub struct SmoothUnion {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub k: Float,
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: Vec3) -> Float {
+        let d1 = self.a.distance(p);
+        let d2 = self.b.distance(p);
+        let h = (0.5 + 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h - self.k * h * (1.0 - h)
+    }
+}
+
+// rotates slices around the y axis by rate radians per unit of height,
+// this stretches distances so pair it with a smaller step scale
+pub struct Twist {
+    pub inner: Arc<dyn Sdf>,
+    pub rate: Float,
+}
+
+impl Sdf for Twist {
+    fn distance(&self, p: Vec3) -> Float {
+        let (s, c) = (self.rate * p.y()).sin_cos();
+        let q = Vec3::new(c * p.x() - s * p.z(), p.y(), s * p.x() + c * p.z());
+        self.inner.distance(q)
+    }
+}
+
+// infinite copies on a grid, a period of zero leaves that axis alone
+pub struct Repeat {
+    pub inner: Arc<dyn Sdf>,
+    pub period: Vec3,
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: Vec3) -> Float {
+        let wrap = |x: Float, period: Float| {
+            if period > 0.0 {
+                x - period * (x / period).round()
+            } else {
+                x
+            }
+        };
+        let q = Vec3::new(
+            wrap(p.x(), self.period.x()),
+            wrap(p.y(), self.period.y()),
+            wrap(p.z(), self.period.z()),
+        );
+        self.inner.distance(q)
+    }
+}
+
+// sphere traced distance field placed at center, bounds are given by the
+// user relative to the center and have to contain the whole surface
+#[derive(Clone)]
+pub struct SdfObject {
+    sdf: Arc<dyn Sdf>,
+    center: Vec3,
+    bounds: AABB,
+    mat: Arc<dyn Scatter>,
+    movement: Vec3,
+    step_scale: Float,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Arc<dyn Sdf>, center: Vec3, bounds: AABB, m: Arc<dyn Scatter>, d: Vec3) -> SdfObject {
+        SdfObject {
+            sdf,
+            center,
+            bounds,
+            mat: m,
+            movement: d,
+            step_scale: 1.0,
+        }
+    }
+
+    // fraction of the distance to step each time, below one for fields
+    // that are not exact like twisted ones
+    pub fn with_step_scale(mut self, step_scale: Float) -> SdfObject {
+        self.step_scale = step_scale;
+        self
+    }
+
+    // central differences, the length is one only for exact fields
+    fn gradient(&self, p: Vec3) -> Vec3 {
+        let d = |x: Float, y: Float, z: Float| self.sdf.distance(p + Vec3::new(x, y, z));
+        let e = EPSILON;
+        Vec3::new(
+            d(e, 0.0, 0.0) - d(-e, 0.0, 0.0),
+            d(0.0, e, 0.0) - d(0.0, -e, 0.0),
+            d(0.0, 0.0, e) - d(0.0, 0.0, -e),
+        ) / (2.0 * e)
+    }
+}
+
+impl Hit for SdfObject {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let local = Ray::new(r.origin() - self.center, r.direction());
+        let (start, end) = self.bounds.clip(&local, t_min, t_max)?;
+
+        let speed = r.direction().length();
+        let mut t = start;
+        // rays starting inside march on the negated distance. on the surface
+        // the sign is noise, there the ray is crossing from the side it is
+        // heading away from
+        let d = self.sdf.distance(local.at(t));
+        let sign = if d.abs() < EPSILON {
+            if r.direction().dot(self.gradient(local.at(t))) < 0.0 { 1.0 } else { -1.0 }
+        } else {
+            d.signum()
+        };
+        for _ in 0..MAX_STEPS {
+            let p = local.at(t);
+            let d = self.sdf.distance(p) * sign;
+            if d < EPSILON {
+                let gradient = self.gradient(p);
+                let mut rec = HitRecord::new(r, t, gradient.unit_vector(), self.mat.as_ref(), (0.0, 0.0));
+                // the march stops up to EPSILON short of the surface in field
+                // units, a spawned ray has to clear that on whichever side it
+                // leaves. twisted fields change slower than distance does
+                rec.p_error += 2.0 * EPSILON / gradient.length();
+                return Some(rec);
+            }
+            t += d * self.step_scale / speed;
+            if t > end {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+impl Object for SdfObject {
+    fn bounding_box(&self) -> AABB {
+        AABB::new(self.bounds.close_corner + self.center, self.bounds.far_corner + self.center)
+    }
+
+    fn step_frame(&self, time_delta: Float) -> Box<dyn Object> {
+        let mut next = self.clone();
+        next.center = self.center + (self.movement * time_delta);
+        Box::new(next)
+    }
+
+    fn material(&self) -> Arc<dyn Scatter> {
+        self.mat.clone()
+    }
+
+    fn movement(&self) -> Vec3 {
+        self.movement
+    }
+
+    fn center(&self) -> Vec3 {
+        self.center
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Capsule, Repeat, RoundedBox, Sdf, SdfObject, SmoothUnion, Torus, Twist};
+    use crate::utils::aabb::AABB;
+    use crate::utils::color::Color;
+    use crate::utils::float::{consts::FRAC_PI_2, Float};
+    use crate::utils::hit::Hit;
+    use crate::utils::lambertian::Lambertian;
+    use crate::utils::ray::Ray;
+    use crate::utils::vec3::Vec3;
+
+    // off the origin so the local transform is exercised too
+    const CENTER: Vec3 = Vec3 { values: [1.0, 2.0, 3.0] };
+
+    fn object(sdf: Arc<dyn Sdf>, half: Vec3) -> SdfObject {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        SdfObject::new(sdf, CENTER, AABB::new(half * -1.0, half), mat, Vec3::new(0.0, 0.0, 0.0))
+    }
+
+    // first hit from origin along direction, both relative to the center
+    fn first_hit(object: &SdfObject, origin: Vec3, direction: Vec3) -> Option<(Float, bool)> {
+        let rec = object.hit(&Ray::new(CENTER + origin, direction), 0.0, Float::INFINITY)?;
+        Some((rec.t, rec.front_face))
+    }
+
+    fn assert_hit(found: Option<(Float, bool)>, t: Float, front_face: bool) {
+        let (found_t, found_front) = found.expect("missed");
+        assert!((found_t - t).abs() < 1e-3 && found_front == front_face, "{} {} instead of {} {}", found_t, found_front, t, front_face);
+    }
+
+    // a bound that touches the surface puts the entry point right on it,
+    // that is a hit and not a reason to look for the far side
+    #[test]
+    fn tight_bound_hits_the_front() {
+        let rounded = object(Arc::new(RoundedBox { half_extents: Vec3::new(1.0, 1.0, 1.0), radius: 0.2 }), Vec3::new(1.0, 1.0, 1.0));
+        assert_hit(first_hit(&rounded, Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 4.0, true);
+        assert_hit(first_hit(&rounded, Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0)), 2.0, true);
+        // from inside the exit is the back face
+        assert_hit(first_hit(&rounded, Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)), 0.5, false);
+        // the rounded corner is cut off
+        assert!(first_hit(&rounded, Vec3::new(-5.0, 0.99, 0.99), Vec3::new(1.0, 0.0, 0.0)).is_none());
+
+        let sphere = object(Arc::new(|p: Vec3| p.length() - 1.0), Vec3::new(1.0, 1.0, 1.0));
+        assert_hit(first_hit(&sphere, Vec3::new(0.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), 4.0, true);
+    }
+
+    #[test]
+    fn shapes() {
+        let torus = object(Arc::new(Torus { major: 1.0, minor: 0.25 }), Vec3::new(1.25, 0.25, 1.25));
+        assert_hit(first_hit(&torus, Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 3.75, true);
+        // down through the hole
+        assert!(first_hit(&torus, Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).is_none());
+        assert_hit(first_hit(&torus, Vec3::new(1.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), 4.75, true);
+
+        let capsule = Capsule { a: Vec3::new(0.0, -1.0, 0.0), b: Vec3::new(0.0, 1.0, 0.0), radius: 0.5 };
+        let capsule = object(Arc::new(capsule), Vec3::new(0.5, 1.5, 0.5));
+        assert_hit(first_hit(&capsule, Vec3::new(-5.0, 0.8, 0.0), Vec3::new(1.0, 0.0, 0.0)), 4.5, true);
+        assert_hit(first_hit(&capsule, Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), 3.5, true);
+    }
+
+    #[test]
+    fn combinators() {
+        let ball = |x: Float| -> Arc<dyn Sdf> { Arc::new(move |p: Vec3| (p - Vec3::new(x, 0.0, 0.0)).length() - 1.0) };
+        let blend = object(
+            Arc::new(SmoothUnion { a: ball(-0.8), b: ball(0.8), k: 0.5 }),
+            Vec3::new(2.0, 1.5, 1.5),
+        );
+        // away from the blend it is just the nearer sphere
+        assert_hit(first_hit(&blend, Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 3.2, true);
+        // between them the blend fills in above where the spheres meet
+        let (t, front) = first_hit(&blend, Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!(front && t < 4.3, "{}", t);
+
+        // the thin side of the slab turns to face x a quarter turn up
+        let slab = Arc::new(RoundedBox { half_extents: Vec3::new(1.0, 2.0, 0.25), radius: 0.0 });
+        let twisted = object(Arc::new(Twist { inner: slab, rate: 1.0 }), Vec3::new(1.0, 2.0, 1.0)).with_step_scale(0.5);
+        assert_hit(first_hit(&twisted, Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 4.0, true);
+        assert_hit(first_hit(&twisted, Vec3::new(-5.0, FRAC_PI_2, 0.0), Vec3::new(1.0, 0.0, 0.0)), 4.75, true);
+        assert_hit(first_hit(&twisted, Vec3::new(0.1, 5.0, 0.1), Vec3::new(0.0, -1.0, 0.0)), 3.0, true);
+
+        // a row of balls on a unit grid in x and z
+        let small = Arc::new(|p: Vec3| p.length() - 0.3);
+        let grid = object(Arc::new(Repeat { inner: small, period: Vec3::new(1.0, 0.0, 1.0) }), Vec3::new(9.5, 0.3, 9.5));
+        assert_hit(first_hit(&grid, Vec3::new(0.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 0.2, true);
+        assert_hit(first_hit(&grid, Vec3::new(-20.0, 0.0, 3.0), Vec3::new(1.0, 0.0, 0.0)), 10.7, true);
+        // between the rows
+        assert!(first_hit(&grid, Vec3::new(-20.0, 0.0, 0.5), Vec3::new(1.0, 0.0, 0.0)).is_none());
+    }
+}
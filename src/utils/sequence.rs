@@ -0,0 +1,45 @@
+use std::io;
+use std::path::Path;
+
+use super::exr::write_exr;
+use super::film::Film;
+use super::netpbm::write_ppm;
+use super::png::write_png;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ImageFormat {
+    Png,
+    Exr,
+    Ppm,
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name {
+            "png" => Some(ImageFormat::Png),
+            "exr" => Some(ImageFormat::Exr),
+            "ppm" => Some(ImageFormat::Ppm),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Exr => "exr",
+            ImageFormat::Ppm => "ppm",
+        }
+    }
+}
+
+// writes one numbered frame of an image sequence, e.g. frame_0020.png.
+// exr keeps the linear radiance, the others are gamma corrected 8 bit
+pub fn write_frame(dir: &Path, frame: usize, format: ImageFormat, film: &Film) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("frame_{:04}.{}", frame, format.extension()));
+    match format {
+        ImageFormat::Png => write_png(&path, film.width, film.height, &film.to_rgb()),
+        ImageFormat::Exr => write_exr(&path, film),
+        ImageFormat::Ppm => write_ppm(&path, film.width, film.height, &film.to_rgb()),
+    }
+}
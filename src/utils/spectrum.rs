@@ -0,0 +1,134 @@
+use std::sync::OnceLock;
+
+use super::color::Color;
+use super::float::Float;
+
+// visible range the spectral renderer samples, in nanometers
+pub const LAMBDA_MIN: Float = 380.0;
+pub const LAMBDA_MAX: Float = 720.0;
+
+// wavelength the rgb renderer uses for dispersive materials (sodium d line)
+pub const LAMBDA_D: Float = 587.6;
+
+// uniform wavelength and its pdf
+pub fn sample_wavelength(u: Float) -> (Float, Float) {
+    let lambda = LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN);
+    (lambda, 1.0 / (LAMBDA_MAX - LAMBDA_MIN))
+}
+
+fn lobe(x: Float, mean: Float, low: Float, high: Float) -> Float {
+    let t = (x - mean) / if x < mean { low } else { high };
+    (-0.5 * t * t).exp()
+}
+
+// cie 1931 2 degree matching functions, multi lobe fit from
+// wyman, sloan and shirley 2013
+pub fn cie_xyz(lambda: Float) -> [Float; 3] {
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    [x, y, z]
+}
+
+#[cfg_attr(feature = "f32", allow(clippy::excessive_precision))]
+pub fn xyz_to_linear_srgb(xyz: [Float; 3]) -> [Float; 3] {
+    let [x, y, z] = xyz;
+    [
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    ]
+}
+
+fn smoothstep(edge0: Float, edge1: Float, x: Float) -> Float {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// rgb to spectrum upsampling with three smooth bands that sum to one
+// everywhere, so white stays flat and reflectances stay within [0, 1]
+pub fn rgb_to_spectrum(color: Color, lambda: Float) -> Float {
+    let [r, g, b] = color.values();
+    let blue = 1.0 - smoothstep(470.0, 520.0, lambda);
+    let red = smoothstep(570.0, 610.0, lambda);
+    let green = 1.0 - blue - red;
+    r * red + g * green + b * blue
+}
+
+// per channel scale that makes a flat unit spectrum come out as rgb white
+fn white_balance() -> &'static [Float; 3] {
+    static WHITE: OnceLock<[Float; 3]> = OnceLock::new();
+    WHITE.get_or_init(|| {
+        let steps = 1000;
+        let dl = (LAMBDA_MAX - LAMBDA_MIN) / steps as Float;
+        let mut xyz = [0.0; 3];
+        for i in 0..steps {
+            let c = cie_xyz(LAMBDA_MIN + (i as Float + 0.5) * dl);
+            for k in 0..3 {
+                xyz[k] += c[k] * dl;
+            }
+        }
+        let rgb = xyz_to_linear_srgb(xyz);
+        [1.0 / rgb[0], 1.0 / rgb[1], 1.0 / rgb[2]]
+    })
+}
+
+// monte carlo estimate of the rgb color of radiance carried at lambda
+pub fn spectral_sample_to_rgb(radiance: Float, lambda: Float, pdf: Float) -> Color {
+    let c = cie_xyz(lambda);
+    let xyz = [c[0] * radiance / pdf, c[1] * radiance / pdf, c[2] * radiance / pdf];
+    let rgb = xyz_to_linear_srgb(xyz);
+    let white = white_balance();
+    Color::new(rgb[0] * white[0], rgb[1] * white[1], rgb[2] * white[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rgb_to_spectrum, sample_wavelength, spectral_sample_to_rgb, LAMBDA_D};
+    use crate::utils::color::Color;
+    use crate::utils::dielectric::Ior;
+    use crate::utils::float::Float;
+
+    // stratified estimate of the rgb a color comes back as after upsampling
+    fn round_trip(color: Color) -> [Float; 3] {
+        let steps = 2000;
+        let mut sum = [0.0; 3];
+        for i in 0..steps {
+            let (lambda, pdf) = sample_wavelength((i as Float + 0.5) / steps as Float);
+            let rgb = spectral_sample_to_rgb(rgb_to_spectrum(color, lambda), lambda, pdf).values();
+            for k in 0..3 {
+                sum[k] += rgb[k] / steps as Float;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn flat_spectrum_is_white() {
+        for v in round_trip(Color::new(1.0, 1.0, 1.0)) {
+            assert!((v - 1.0).abs() < 1e-3, "{}", v);
+        }
+        for v in round_trip(Color::new(0.4, 0.4, 0.4)) {
+            assert!((v - 0.4).abs() < 1e-3, "{}", v);
+        }
+        // a saturated color still comes back with its dominant channel
+        let [r, g, b] = round_trip(Color::new(1.0, 0.0, 0.0));
+        assert!(r > 0.5 && r > 2.0 * g && r > 2.0 * b, "{} {} {}", r, g, b);
+    }
+
+    #[test]
+    fn glass_is_about_one_and_a_half() {
+        let cauchy = Ior::Cauchy { a: 1.46, b: 0.0138 };
+        assert!((cauchy.at(LAMBDA_D) - 1.5).abs() < 1e-3, "{}", cauchy.at(LAMBDA_D));
+        assert!((Ior::BK7.at(LAMBDA_D) - 1.5168).abs() < 1e-4, "{}", Ior::BK7.at(LAMBDA_D));
+        // normal dispersion, blue bends more than red
+        for ior in [cauchy, Ior::BK7] {
+            assert!(ior.at(450.0) > ior.at(LAMBDA_D) && ior.at(LAMBDA_D) > ior.at(650.0));
+        }
+        assert_eq!(Ior::Constant(1.33).at(400.0), 1.33);
+    }
+}
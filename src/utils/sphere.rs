@@ -1,5 +1,8 @@
+use super::aabb::AABB;
+use super::float::{consts::PI, gamma, Float};
 use super::hit::Hit;
 use super::hit_record::HitRecord;
+use super::object::Object;
 use super::ray::Ray;
 use super::scatter::Scatter;
 use super::vec3::Vec3;
@@ -8,13 +11,13 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct Sphere {
     center: Vec3,
-    radius: f64,
+    radius: Float,
     mat: Arc<dyn Scatter>,
     movement: Vec3,
 }
 
 impl Sphere {
-    pub fn new(c: Vec3, r: f64, m: Arc<dyn Scatter>, d: Vec3) -> Sphere {
+    pub fn new(c: Vec3, r: Float, m: Arc<dyn Scatter>, d: Vec3) -> Sphere {
         Sphere {
             center: c,
             radius: r,
@@ -33,46 +36,107 @@ impl Sphere {
         self.center + Vec3::new(r, r, r)
     }
 
-    pub fn step_frame(&self, time_delta: f64) -> Sphere{
+    pub fn step_frame(&self, time_delta: Float) -> Sphere{
         Sphere::new(self.center + (self.movement * time_delta), self.radius, self.mat.clone(), self.movement)
     }
 }
 
-impl Hit for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+impl Sphere {
+    // nearest intersection distance within the range
+    fn root(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<Float> {
         // Do we get hit?
         let oc = r.origin() - self.center;
-        let a = r.direction().length().powi(2);
+        let a = r.direction().length_squared();
         let half_b = oc.dot(r.direction());
-        let c = oc.length().powi(2) - self.radius * self.radius;
+        let c = oc.length_squared() - self.radius * self.radius;
 
-        let discriminant = half_b.powi(2) - a * c;
+        // half_b^2 - a c rewritten around the point of the line closest to
+        // the center, which keeps its digits when the sphere is far away
+        // or much larger than the distance to it
+        let closest = oc - r.direction() * (half_b / a);
+        let discriminant = a * (self.radius * self.radius - closest.length_squared());
         if discriminant < 0.0 {
             return None;
         }
 
-        // Find the nearest root in the range
-        let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
+        // Find the nearest root in the range, the smaller one in magnitude
+        // comes from the product of the roots to avoid cancellation
+        let q = -(half_b + half_b.signum() * discriminant.sqrt());
+        let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+        let (near, far) = (t0.min(t1), t0.max(t1));
+        if near >= t_min && near <= t_max {
+            Some(near)
+        } else if far >= t_min && far <= t_max {
+            Some(far)
+        } else {
+            None
         }
+    }
+}
 
-        let point = r.at(root);
-        let mut rec = HitRecord {
-            t: root,
-            p: point,
-            mat: self.mat.clone(),
-            normal: Vec3::new(0.0, 0.0, 0.0),
-            front_face: false,
-        };
+impl Hit for Sphere {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let root = self.root(r, t_min, t_max)?;
 
-        let outward_normal = (point - self.center) / self.radius;
-        rec.set_face_normal(r, outward_normal);
+        // a negative radius turns the normals inwards
+        let direction = (r.at(root) - self.center).unit_vector();
+        let outward_normal = direction * self.radius.signum();
+        let u = (-outward_normal.z()).atan2(outward_normal.x()) / (2.0 * PI) + 0.5;
+        let v = (-outward_normal.y()).acos() / PI;
 
+        // the point is put back on the surface, which bounds its error by
+        // the size of the sphere instead of the error in the root
+        let mut rec = HitRecord::new(r, root, outward_normal, self.mat.as_ref(), (u, v));
+        rec.p = self.center + direction * self.radius.abs();
+        rec.p_error = gamma(8) * (self.center.length() + self.radius.abs());
         Some(rec)
     }
+
+    fn occluded(&self, r: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.root(r, t_min, t_max).is_some()
+    }
+}
+
+impl Object for Sphere {
+    fn bounding_box(&self) -> AABB {
+        AABB::new(self.lower_bound(), self.upper_bound())
+    }
+
+    fn step_frame(&self, time_delta: Float) -> Box<dyn Object> {
+        Box::new(Sphere::step_frame(self, time_delta))
+    }
+
+    fn material(&self) -> Arc<dyn Scatter> {
+        self.mat.clone()
+    }
+
+    fn movement(&self) -> Vec3 {
+        self.movement
+    }
+
+    fn center(&self) -> Vec3 {
+        self.center
+    }
+
+    fn distance(&self, p: Vec3) -> Float {
+        ((p - self.center).length() - self.radius).max(0.0)
+    }
+
+    // touching spheres are a ray against a sphere of both radii
+    fn sphere_cast(&self, r: &Ray, radius: Float, t_max: Float) -> Option<Float> {
+        let reach = self.radius + radius;
+        let oc = r.origin() - self.center;
+        let c = oc.length_squared() - reach * reach;
+        if c <= 0.0 {
+            return Some(0.0);
+        }
+        let a = r.direction().length_squared();
+        let half_b = oc.dot(r.direction());
+        let discriminant = half_b * half_b - a * c;
+        if half_b >= 0.0 || discriminant < 0.0 {
+            return None;
+        }
+        let t = (-half_b - discriminant.sqrt()) / a;
+        if t <= t_max { Some(t) } else { None }
+    }
 }
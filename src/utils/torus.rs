@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use super::aabb::AABB;
+use super::float::{consts::PI, Float};
+use super::frame::{azimuth, Frame};
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::object::Object;
+use super::ray::Ray;
+use super::roots::solve_quartic;
+use super::scatter::Scatter;
+use super::vec3::Vec3;
+
+// ring around axis, major is the distance from the center to the middle
+// of the tube and minor the radius of the tube
+#[derive(Clone)]
+pub struct Torus {
+    frame: Frame,
+    major: Float,
+    minor: Float,
+    mat: Arc<dyn Scatter>,
+    movement: Vec3,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major: Float, minor: Float, m: Arc<dyn Scatter>, d: Vec3) -> Torus {
+        Torus {
+            frame: Frame::new(center, axis),
+            major,
+            minor,
+            mat: m,
+            movement: d,
+        }
+    }
+}
+
+impl Hit for Torus {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // skip the quartic for rays that miss the bounds
+        let bounds = self.bounding_box();
+        let (t_min, t_max) = bounds.clip(r, t_min, t_max)?;
+
+        // solve from where the ray enters the bounds, from far away the
+        // coefficients would lose every digit that describes the tube
+        let o = self.frame.to_local(r.at(t_min));
+        let d = self.frame.dir_to_local(r.direction());
+        // solve with a unit direction to keep the coefficients well scaled
+        let speed = d.length();
+        let d = d / speed;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) along the ray
+        let r2 = self.major * self.major;
+        let e = o.length_squared() - r2 - self.minor * self.minor;
+        let f = o.dot(d);
+        let roots = solve_quartic(
+            4.0 * f,
+            2.0 * e + 4.0 * f * f + 4.0 * r2 * d.y().powi(2),
+            4.0 * f * e + 8.0 * r2 * o.y() * d.y(),
+            e * e - 4.0 * r2 * (self.minor * self.minor - o.y().powi(2)),
+        );
+        let s = roots
+            .into_iter()
+            .filter(|&s| s >= 0.0 && t_min + s / speed <= t_max)
+            .fold(Float::INFINITY, Float::min);
+        if !s.is_finite() {
+            return None;
+        }
+
+        let p = o + d * s;
+        let k = p.length_squared() - r2 - self.minor * self.minor;
+        let normal = Vec3::new(p.x() * k, p.y() * (k + 2.0 * r2), p.z() * k).unit_vector();
+
+        // angle around the tube, measured from the outer equator
+        let ring = (p.x().powi(2) + p.z().powi(2)).sqrt() - self.major;
+        let v = (p.y().atan2(ring) + PI) / (2.0 * PI);
+
+        // roots of the quartic keep about half their digits near grazing
+        // hits, more than the closed form bound allows for
+        let mut rec = HitRecord::new(r, t_min + s / speed, self.frame.dir_to_world(normal), self.mat.as_ref(), (azimuth(p), v));
+        rec.p_error = rec.p_error.max(Float::EPSILON.sqrt() * (self.major + self.minor));
+        Some(rec)
+    }
+}
+
+impl Object for Torus {
+    fn bounding_box(&self) -> AABB {
+        let w = self.major + self.minor;
+        self.frame.bounds(Vec3::new(-w, -self.minor, -w), Vec3::new(w, self.minor, w))
+    }
+
+    fn step_frame(&self, time_delta: Float) -> Box<dyn Object> {
+        let mut next = self.clone();
+        next.frame = self.frame.translated(self.movement * time_delta);
+        Box::new(next)
+    }
+
+    fn material(&self) -> Arc<dyn Scatter> {
+        self.mat.clone()
+    }
+
+    fn movement(&self) -> Vec3 {
+        self.movement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Torus;
+    use crate::utils::color::Color;
+    use crate::utils::float::Float;
+    use crate::utils::hit::Hit;
+    use crate::utils::lambertian::Lambertian;
+    use crate::utils::ray::Ray;
+    use crate::utils::vec3::Vec3;
+
+    // the distance to the torus dwarfs the tube, the hit still has to
+    // land on the tube and not somewhere the quartic rounded it to
+    #[test]
+    fn hits_from_far_away() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let torus = Torus::new(center, Vec3::new(0.0, 1.0, 0.0), 1.0, 0.25, mat, Vec3::new(0.0, 0.0, 0.0));
+        for far in [10.0, 100.0, 1000.0, 10000.0] {
+            let hit = |origin: Vec3, direction: Vec3| {
+                torus.hit(&Ray::new(center + origin, direction), 0.0, Float::INFINITY).map(|rec| rec.t)
+            };
+            let close = |t: Option<Float>, expected: Float| {
+                t.is_some_and(|t| (t - expected).abs() <= 1e-5 * expected)
+            };
+
+            let t = hit(Vec3::new(-far, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+            assert!(close(t, far - 1.25), "{:?} from {}", t, far);
+            let t = hit(Vec3::new(1.0, 0.0, -far), Vec3::new(0.0, 0.0, 2.0));
+            assert!(close(t, (far - 0.75) / 2.0), "{:?} from {}", t, far);
+            // straight through the hole and just over the top
+            assert_eq!(hit(Vec3::new(0.0, far, 0.0), Vec3::new(0.0, -1.0, 0.0)), None, "from {}", far);
+            assert_eq!(hit(Vec3::new(-far, 0.3, 0.0), Vec3::new(1.0, 0.0, 0.0)), None, "from {}", far);
+        }
+    }
+}
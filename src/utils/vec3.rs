@@ -1,24 +1,25 @@
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub};
 
 use rand::Rng;
+use super::float::Float;
 
 #[derive(Copy, Clone)]
 pub struct Vec3 {
-    pub values: [f64; 3],
+    pub values: [Float; 3],
 }
 
 impl Vec3 {
-    pub fn new(x: f64, y: f64, z: f64) -> Self {
+    pub fn new(x: Float, y: Float, z: Float) -> Self {
         Vec3 { values: [x, y, z] }
     }
 
-    pub fn x(&self) -> f64 {
+    pub fn x(&self) -> Float {
         self.values[0]
     }
-    pub fn y(&self) -> f64 {
+    pub fn y(&self) -> Float {
         self.values[1]
     }
-    pub fn z(&self) -> f64 {
+    pub fn z(&self) -> Float {
         self.values[2]
     }
 
@@ -32,7 +33,7 @@ impl Vec3 {
         *self / self.length()
     }
 
-    pub fn length(&self) -> f64 {
+    pub fn length(&self) -> Float {
         self.length_squared().sqrt()
     }
 
@@ -46,15 +47,15 @@ impl Vec3 {
         }
     }
 
-    pub fn dot(&self, other: Vec3) -> f64 {
+    pub fn dot(&self, other: Vec3) -> Float {
         self.x() * other.x() + self.y() * other.y() + self.z() * other.z()
     }
 
-    pub fn length_squared(&self) -> f64 {
+    pub fn length_squared(&self) -> Float {
         self.x() * self.x() + self.y() * self.y() + self.z() * self.z()
     }
 
-    pub fn random(lower: f64, upper: f64) -> Vec3 {
+    pub fn random(lower: Float, upper: Float) -> Vec3 {
         let mut rng = rand::thread_rng();
         Vec3 {
             values: [
@@ -75,7 +76,7 @@ impl Vec3 {
     }
 
     pub fn near_zero(self) -> bool {
-        const TINY_VALUE: f64 = 1.0e-8;
+        const TINY_VALUE: Float = 1.0e-8;
         self.x().abs() < TINY_VALUE && self.y().abs() < TINY_VALUE && self.z().abs() < TINY_VALUE
     }
 
@@ -83,7 +84,7 @@ impl Vec3 {
         self - n * 2.0 * self.dot(n)
     }
 
-    pub fn refract(self, n: Vec3, etai_over_etat: f64) -> Vec3 {
+    pub fn refract(self, n: Vec3, etai_over_etat: Float) -> Vec3 {
         let cos_theta = (self * (-1.0)).dot(n).min(1.0);
         let r_out_perp = (self + (n * cos_theta)) * etai_over_etat;
         let r_out_parallel = n * -((1.0 - r_out_perp.length_squared().abs()).sqrt());
@@ -126,18 +127,18 @@ impl AddAssign for Vec3 {
     }
 }
 
-impl Mul<f64> for Vec3 {
+impl Mul<Float> for Vec3 {
     type Output = Vec3;
 
-    fn mul(self, other: f64) -> Vec3 {
+    fn mul(self, other: Float) -> Vec3 {
         Vec3 {
             values: [self.x() * other, self.y() * other, self.z() * other],
         }
     }
 }
 
-impl MulAssign<f64> for Vec3 {
-    fn mul_assign(&mut self, other: f64) {
+impl MulAssign<Float> for Vec3 {
+    fn mul_assign(&mut self, other: Float) {
         *self = Vec3 {
             values: [self.x() * other, self.y() * other, self.z() * other],
         }
@@ -158,18 +159,18 @@ impl Sub for Vec3 {
     }
 }
 
-impl Div<f64> for Vec3 {
+impl Div<Float> for Vec3 {
     type Output = Vec3;
 
-    fn div(self, other: f64) -> Self::Output {
+    fn div(self, other: Float) -> Self::Output {
         Vec3 {
             values: [self.x() / other, self.y() / other, self.z() / other],
         }
     }
 }
 
-impl DivAssign<f64> for Vec3 {
-    fn div_assign(&mut self, other: f64) {
+impl DivAssign<Float> for Vec3 {
+    fn div_assign(&mut self, other: Float) {
         *self = Vec3 {
             values: [self.x() / other, self.y() / other, self.z() / other],
         }
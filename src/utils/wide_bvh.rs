@@ -0,0 +1,540 @@
+use super::aabb::Tree;
+use super::float::Float;
+use super::hit::Hit;
+use super::hit_record::HitRecord;
+use super::packet::Frustum;
+use super::ray::Ray;
+
+// enough for the deepest collapsed tree times the children pushed per node
+const STACK_SIZE: usize = 1024;
+// marks a child slot that points into the items instead of at a node
+const LEAF: u32 = 1 << 31;
+// bounds are rounded outwards but the ray is rounded to f32 too, widening
+// the exit distance keeps the test conservative
+const SLACK: f32 = 1.0 + 4.0 * f32::EPSILON;
+
+// children of a node stored axis by axis so one load covers every child.
+// unused slots have all bounds at infinity, which no ray can enter
+#[derive(Clone, Copy)]
+#[repr(C, align(32))]
+struct WideNode<const N: usize> {
+    lower: [[f32; N]; 3],
+    upper: [[f32; N]; 3],
+    // index of the child node, or LEAF | first item for leaf children
+    child: [u32; N],
+    // number of items in leaf children
+    count: [u16; N],
+}
+
+impl<const N: usize> WideNode<N> {
+    fn empty() -> WideNode<N> {
+        WideNode {
+            lower: [[f32::INFINITY; N]; 3],
+            upper: [[f32::INFINITY; N]; 3],
+            child: [0; N],
+            count: [0; N],
+        }
+    }
+}
+
+// ray rounded to f32 for the box tests
+struct WideRay {
+    origin: [f32; 3],
+    inv: [f32; 3],
+    t_min: f32,
+}
+
+impl WideRay {
+    #[cfg_attr(feature = "f32", allow(clippy::unnecessary_cast))]
+    fn new(r: &Ray, t_min: Float) -> WideRay {
+        WideRay {
+            origin: r.origin().values.map(|v| v as f32),
+            inv: r.inv_direction().values.map(|v| v as f32),
+            t_min: (t_min as f32).next_down(),
+        }
+    }
+}
+
+// entry distance into each child, infinity for the ones the ray misses
+fn slab_scalar(lower: &[&[f32]; 3], upper: &[&[f32]; 3], ray: &WideRay, t_max: f32, out: &mut [f32]) {
+    for (i, d) in out.iter_mut().enumerate() {
+        let (mut t0, mut t1) = (ray.t_min, t_max);
+        for axis in 0..3 {
+            let a = (lower[axis][i] - ray.origin[axis]) * ray.inv[axis];
+            let b = (upper[axis][i] - ray.origin[axis]) * ray.inv[axis];
+            t0 = t0.max(a.min(b));
+            t1 = t1.min(a.max(b));
+        }
+        *d = if t0 <= t1 * SLACK { t0 } else { f32::INFINITY };
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::{WideRay, SLACK};
+
+    // sse2 is part of the x86_64 baseline so this needs no detection
+    pub fn slab4(lower: &[&[f32]; 3], upper: &[&[f32]; 3], ray: &WideRay, t_max: f32, out: &mut [f32]) {
+        assert!(out.len() == 4 && lower.iter().chain(upper).all(|s| s.len() == 4));
+        unsafe {
+            let mut t0 = _mm_set1_ps(ray.t_min);
+            let mut t1 = _mm_set1_ps(t_max);
+            for axis in 0..3 {
+                let o = _mm_set1_ps(ray.origin[axis]);
+                let inv = _mm_set1_ps(ray.inv[axis]);
+                let a = _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(lower[axis].as_ptr()), o), inv);
+                let b = _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(upper[axis].as_ptr()), o), inv);
+                // min and max return their second operand when either is
+                // nan, the running bounds go second so a ray lying in a
+                // slab plane keeps them like the scalar test does
+                t0 = _mm_max_ps(_mm_min_ps(a, b), t0);
+                t1 = _mm_min_ps(_mm_max_ps(a, b), t1);
+            }
+            let hit = _mm_cmple_ps(t0, _mm_mul_ps(t1, _mm_set1_ps(SLACK)));
+            let miss = _mm_andnot_ps(hit, _mm_set1_ps(f32::INFINITY));
+            _mm_storeu_ps(out.as_mut_ptr(), _mm_or_ps(_mm_and_ps(hit, t0), miss));
+        }
+    }
+
+    // callers check for avx at runtime
+    #[target_feature(enable = "avx")]
+    pub unsafe fn slab8(lower: &[&[f32]; 3], upper: &[&[f32]; 3], ray: &WideRay, t_max: f32, out: &mut [f32]) {
+        assert!(out.len() == 8 && lower.iter().chain(upper).all(|s| s.len() == 8));
+        unsafe {
+            let mut t0 = _mm256_set1_ps(ray.t_min);
+            let mut t1 = _mm256_set1_ps(t_max);
+            for axis in 0..3 {
+                let o = _mm256_set1_ps(ray.origin[axis]);
+                let inv = _mm256_set1_ps(ray.inv[axis]);
+                let a = _mm256_mul_ps(_mm256_sub_ps(_mm256_loadu_ps(lower[axis].as_ptr()), o), inv);
+                let b = _mm256_mul_ps(_mm256_sub_ps(_mm256_loadu_ps(upper[axis].as_ptr()), o), inv);
+                t0 = _mm256_max_ps(_mm256_min_ps(a, b), t0);
+                t1 = _mm256_min_ps(_mm256_max_ps(a, b), t1);
+            }
+            let hit = _mm256_cmp_ps::<_CMP_LE_OQ>(t0, _mm256_mul_ps(t1, _mm256_set1_ps(SLACK)));
+            let dist = _mm256_blendv_ps(_mm256_set1_ps(f32::INFINITY), t0, hit);
+            _mm256_storeu_ps(out.as_mut_ptr(), dist);
+        }
+    }
+}
+
+// read only copy of a tree where every node has up to N children, made by
+// pulling grandchildren up into their parents. the child boxes of a node
+// are tested together with simd where the cpu has it
+pub struct WideBvh<'a, const N: usize> {
+    tree: &'a Tree,
+    nodes: Vec<WideNode<N>>,
+    items: Vec<u32>,
+    avx: bool,
+}
+
+pub type Bvh4<'a> = WideBvh<'a, 4>;
+pub type Bvh8<'a> = WideBvh<'a, 8>;
+
+impl<'a, const N: usize> WideBvh<'a, N> {
+    pub fn new(tree: &'a Tree) -> WideBvh<'a, N> {
+        assert!(N >= 2);
+        #[cfg(target_arch = "x86_64")]
+        let avx = is_x86_feature_detected!("avx");
+        #[cfg(not(target_arch = "x86_64"))]
+        let avx = false;
+
+        let mut bvh = WideBvh {
+            tree,
+            nodes: Vec::with_capacity(tree.node_count() / (N - 1) + 1),
+            items: Vec::with_capacity(tree.len()),
+            avx,
+        };
+        if let Some(root) = tree.root() {
+            bvh.collapse(root);
+        }
+        bvh
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[cfg_attr(feature = "f32", allow(clippy::unnecessary_cast))]
+    fn collapse(&mut self, node: usize) -> u32 {
+        let idx = self.nodes.len();
+        self.nodes.push(WideNode::empty());
+
+        // open up the largest interior child until the node is full
+        let mut slots = match self.tree.children(node) {
+            Some((left, right)) => vec![left, right],
+            None => vec![node],
+        };
+        while slots.len() < N {
+            let widest = (0..slots.len())
+                .filter(|&i| self.tree.children(slots[i]).is_some())
+                .max_by(|&a, &b| {
+                    let area = |i: usize| self.tree.node_aabb(slots[i]).surface_area;
+                    area(a).total_cmp(&area(b))
+                });
+            match widest {
+                Some(i) => {
+                    let (left, right) = self.tree.children(slots[i]).unwrap();
+                    slots[i] = left;
+                    slots.push(right);
+                }
+                None => break,
+            }
+        }
+
+        for (slot, &child) in slots.iter().enumerate() {
+            let aabb = self.tree.node_aabb(child);
+            for axis in 0..3 {
+                self.nodes[idx].lower[axis][slot] = (aabb.close_corner.values[axis] as f32).next_down();
+                self.nodes[idx].upper[axis][slot] = (aabb.far_corner.values[axis] as f32).next_up();
+            }
+            if self.tree.children(child).is_none() {
+                let items = self.tree.items(child);
+                self.nodes[idx].child[slot] = LEAF | self.items.len() as u32;
+                self.nodes[idx].count[slot] = u16::try_from(items.len()).expect("leaf too large");
+                self.items.extend(items.iter().map(|&i| i as u32));
+            } else {
+                self.nodes[idx].child[slot] = self.collapse(child);
+            }
+        }
+        idx as u32
+    }
+
+    #[inline]
+    fn intersect(&self, node: &WideNode<N>, ray: &WideRay, t_max: f32, out: &mut [f32; N]) {
+        let lower = [&node.lower[0][..], &node.lower[1][..], &node.lower[2][..]];
+        let upper = [&node.upper[0][..], &node.upper[1][..], &node.upper[2][..]];
+        #[cfg(target_arch = "x86_64")]
+        {
+            if N == 4 {
+                return x86::slab4(&lower, &upper, ray, t_max, out);
+            }
+            if N == 8 && self.avx {
+                // avx support was checked when the bvh was built
+                return unsafe { x86::slab8(&lower, &upper, ray, t_max, out) };
+            }
+        }
+        slab_scalar(&lower, &upper, ray, t_max, out)
+    }
+
+    // whether a single ray enters one child of a node before t_max
+    #[cfg_attr(feature = "f32", allow(clippy::unnecessary_cast))]
+    fn enters(&self, node: &WideNode<N>, slot: usize, ray: &WideRay, t_max: Float) -> bool {
+        let lower = [0, 1, 2].map(|axis| &node.lower[axis][slot..=slot]);
+        let upper = [0, 1, 2].map(|axis| &node.upper[axis][slot..=slot]);
+        let mut dist = [0f32];
+        slab_scalar(&lower, &upper, ray, (t_max as f32).next_up(), &mut dist);
+        dist[0] < f32::INFINITY
+    }
+}
+
+impl<const N: usize> Hit for WideBvh<'_, N> {
+    #[cfg_attr(feature = "f32", allow(clippy::unnecessary_cast))]
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let ray = WideRay::new(r, t_min);
+
+        // entries are (entry distance, child, leaf item count)
+        let mut stack = [(0f32, 0u32, 0u16); STACK_SIZE];
+        stack[0] = (ray.t_min, 0, 0);
+        let mut sp = 1;
+        let mut closest = t_max;
+        let mut best = None;
+        let mut dist = [0f32; N];
+        while sp > 0 {
+            sp -= 1;
+            let (t_enter, child, count) = stack[sp];
+            if t_enter as Float > closest {
+                continue;
+            }
+
+            if child & LEAF != 0 {
+                let start = (child & !LEAF) as usize;
+                for &item in &self.items[start..start + count as usize] {
+                    if let Some(mut rec) = self.tree.object(item as usize).hit(r, t_min, closest) {
+                        closest = rec.t;
+                        rec.object_id = item as usize;
+                        best = Some(rec);
+                    }
+                }
+                continue;
+            }
+
+            let node = &self.nodes[child as usize];
+            self.intersect(node, &ray, (closest as f32).next_up(), &mut dist);
+            // push far children first so the nearest is popped next
+            let start = sp;
+            for (slot, &d) in dist.iter().enumerate() {
+                if d < f32::INFINITY {
+                    stack[sp] = (d, node.child[slot], node.count[slot]);
+                    sp += 1;
+                }
+            }
+            stack[start..sp].sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+        }
+        best
+    }
+
+    #[cfg_attr(feature = "f32", allow(clippy::unnecessary_cast))]
+    fn occluded(&self, r: &Ray, t_min: Float, t_max: Float) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let ray = WideRay::new(r, t_min);
+        let t_far = (t_max as f32).next_up();
+        let mut stack = [0u32; STACK_SIZE];
+        let mut sp = 1;
+        let mut dist = [0f32; N];
+        while sp > 0 {
+            sp -= 1;
+            let node = &self.nodes[stack[sp] as usize];
+            self.intersect(node, &ray, t_far, &mut dist);
+            for (slot, &d) in dist.iter().enumerate() {
+                if d == f32::INFINITY {
+                    continue;
+                }
+                let child = node.child[slot];
+                if child & LEAF == 0 {
+                    stack[sp] = child;
+                    sp += 1;
+                    continue;
+                }
+                let start = (child & !LEAF) as usize;
+                let items = &self.items[start..start + node.count[slot] as usize];
+                if items.iter().any(|&i| self.tree.object(i as usize).occluded(r, t_min, t_max)) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // walks the tree once for the whole packet, culling nodes with the
+    // packet's frustum. leaves are still tested ray by ray
+    fn hit_packet(&self, rays: &[Ray], t_min: Float, t_max: Float) -> Vec<Option<HitRecord<'_>>> {
+        let frustum = match Frustum::new(rays) {
+            Some(frustum) if rays.len() > 1 && !self.nodes.is_empty() => frustum,
+            _ => return rays.iter().map(|r| self.hit(r, t_min, t_max)).collect(),
+        };
+        let wide: Vec<WideRay> = rays.iter().map(|r| WideRay::new(r, t_min)).collect();
+
+        let mut closest = vec![t_max; rays.len()];
+        let mut best: Vec<Option<HitRecord>> = rays.iter().map(|_| None).collect();
+        // no node is worth visiting past the farthest of the closest hits
+        let mut farthest = t_max;
+
+        // entries are (entry distance, parent node, slot in the parent),
+        // the root has no parent and is marked with u32::MAX
+        let mut stack = Vec::with_capacity(64);
+        stack.push((t_min, u32::MAX, 0));
+        while let Some((t_enter, parent, slot)) = stack.pop() {
+            if t_enter > farthest {
+                continue;
+            }
+            let (child, count) = if parent == u32::MAX {
+                (0, 0)
+            } else {
+                let p = &self.nodes[parent as usize];
+                (p.child[slot], p.count[slot])
+            };
+
+            if child & LEAF != 0 {
+                let p = &self.nodes[parent as usize];
+                let start = (child & !LEAF) as usize;
+                let items = &self.items[start..start + count as usize];
+                for (i, r) in rays.iter().enumerate() {
+                    if !self.enters(p, slot, &wide[i], closest[i]) {
+                        continue;
+                    }
+                    for &item in items {
+                        if let Some(mut rec) = self.tree.object(item as usize).hit(r, t_min, closest[i]) {
+                            closest[i] = rec.t;
+                            rec.object_id = item as usize;
+                            best[i] = Some(rec);
+                        }
+                    }
+                }
+                farthest = closest.iter().cloned().fold(Float::NEG_INFINITY, Float::max);
+                continue;
+            }
+
+            let node = &self.nodes[child as usize];
+            let start = stack.len();
+            for s in 0..N {
+                // unused slots
+                if node.lower[0][s] == f32::INFINITY {
+                    continue;
+                }
+                let lower = [0, 1, 2].map(|axis| node.lower[axis][s] as Float);
+                let upper = [0, 1, 2].map(|axis| node.upper[axis][s] as Float);
+                if let Some(t) = frustum.enter(lower, upper, t_min, farthest) {
+                    stack.push((t, child, s));
+                }
+            }
+            // nearest child on top
+            stack[start..].sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+        }
+        best
+    }
+}
+
+// widest layout the cpu can test in one go, for rendering
+pub fn compile(tree: &Tree) -> Box<dyn Hit + '_> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            return Box::new(Bvh8::new(tree));
+        }
+    }
+    Box::new(Bvh4::new(tree))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{slab_scalar, Bvh4, Bvh8, WideRay};
+    use crate::utils::aabb::Tree;
+    use crate::utils::color::Color;
+    use crate::utils::float::Float;
+    use crate::utils::hit::Hit;
+    use crate::utils::lambertian::Lambertian;
+    use crate::utils::object::Object;
+    use crate::utils::packet::Frustum;
+    use crate::utils::ray::Ray;
+    use crate::utils::sphere::Sphere;
+    use crate::utils::vec3::Vec3;
+
+    // a ray lying in the plane of a flat box gives 0 * inf = nan on that
+    // axis, which has to leave the running bounds alone
+    #[test]
+    fn slabs_ignore_nan() {
+        let ray = WideRay::new(&Ray::new(Vec3::new(-5.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0)), 0.0);
+        let mut lower = [[f32::INFINITY; 8]; 3];
+        let mut upper = [[f32::INFINITY; 8]; 3];
+        // flat in z right where the ray is, an ordinary box around the ray
+        // and one beside it. z goes last so no later axis can hide the nan
+        let boxes = [([-1.0, -1.0, 1.0], [1.0, 1.0, 1.0]), ([-1.0, -1.0, -1.0], [1.0, 1.0, 2.0]), ([-1.0, 3.0, -1.0], [1.0, 4.0, 1.0])];
+        for (slot, (lo, hi)) in boxes.iter().enumerate() {
+            for axis in 0..3 {
+                lower[axis][slot] = lo[axis];
+                upper[axis][slot] = hi[axis];
+            }
+        }
+        fn slices(b: &[[f32; 8]; 3], n: usize) -> [&[f32]; 3] {
+            [&b[0][..n], &b[1][..n], &b[2][..n]]
+        }
+
+        let mut expected = [0.0; 8];
+        slab_scalar(&slices(&lower, 8), &slices(&upper, 8), &ray, f32::INFINITY, &mut expected);
+        assert_eq!(expected[..3], [4.0, 4.0, f32::INFINITY]);
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            let mut dist = [0.0; 4];
+            super::x86::slab4(&slices(&lower, 4), &slices(&upper, 4), &ray, f32::INFINITY, &mut dist);
+            assert_eq!(dist, expected[..4]);
+            if is_x86_feature_detected!("avx") {
+                let mut dist = [0.0; 8];
+                unsafe { super::x86::slab8(&slices(&lower, 8), &slices(&upper, 8), &ray, f32::INFINITY, &mut dist) };
+                assert_eq!(dist, expected);
+            }
+        }
+    }
+
+    // axis aligned rays starting on the faces of the object boxes, where
+    // the zero direction components meet origins on slab planes
+    #[test]
+    fn axis_aligned_rays_from_box_faces() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let objects: Vec<Box<dyn Object>> = (0..200)
+            .map(|i| {
+                let center = Vec3::new((i % 6) as Float, ((i / 6) % 6) as Float, (i / 36) as Float) * 1.5;
+                Box::new(Sphere::new(center, rng.gen_range(0.25..1.0), mat.clone(), Vec3::new(0.0, 0.0, 0.0))) as Box<dyn Object>
+            })
+            .collect();
+        let tree = Tree::build(objects, 4);
+        let (bvh4, bvh8) = (Bvh4::new(&tree), Bvh8::new(&tree));
+        // wider nodes collapse more of the binary tree into each one
+        assert!(bvh8.node_count() > 0 && bvh8.node_count() < bvh4.node_count());
+
+        let axes = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)];
+        for i in 0..tree.len() {
+            let aabb = tree.object(i).bounding_box();
+            for face in 0..3 {
+                for corner in [aabb.close_corner, aabb.far_corner] {
+                    let mut origin = aabb.close_corner + (aabb.far_corner - aabb.close_corner) * rng.gen_range(0.0..1.0);
+                    origin.values[face] = corner.values[face];
+                    for axis in axes {
+                        for direction in [axis, axis * -1.0] {
+                            let r = Ray::new(origin, direction);
+                            let expected = tree.hit(&r, 0.0, Float::INFINITY).map(|rec| rec.t);
+                            assert_eq!(bvh4.hit(&r, 0.0, Float::INFINITY).map(|rec| rec.t), expected);
+                            assert_eq!(bvh8.hit(&r, 0.0, Float::INFINITY).map(|rec| rec.t), expected);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // 8x8 tiles of a thin lens camera looking down -z at the spheres, every
+    // ray starts somewhere else on the aperture. tiles near the middle
+    // column have rays on both sides of x = 0 and the outer ones see sky
+    #[test]
+    fn lens_packets_match_single_rays() {
+        let mut rng = StdRng::seed_from_u64(13);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let objects: Vec<Box<dyn Object>> = (0..150)
+            .map(|_| {
+                let center = Vec3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+                Box::new(Sphere::new(center, rng.gen_range(0.2..1.0), mat.clone(), Vec3::new(0.0, 0.0, 0.0))) as Box<dyn Object>
+            })
+            .collect();
+        let tree = Tree::build(objects, 4);
+        let (bvh4, bvh8) = (Bvh4::new(&tree), Bvh8::new(&tree));
+
+        let camera = Vec3::new(0.0, 0.0, 30.0);
+        let (mut split, mut misses, mut hits) = (0, 0, 0);
+        for tile_y in 0..8 {
+            for tile_x in 0..8 {
+                let rays: Vec<Ray> = (0..64)
+                    .map(|i| {
+                        let px = (tile_x * 8 + i % 8) as Float + 0.5;
+                        let py = (tile_y * 8 + i / 8) as Float + 0.5;
+                        let focus = Vec3::new(px / 64.0 * 40.0 - 20.0, py / 64.0 * 40.0 - 20.0, 0.0);
+                        let lens = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+                        Ray::new(camera + lens, focus - camera - lens)
+                    })
+                    .collect();
+                if Frustum::new(&rays).is_none() {
+                    split += 1;
+                }
+
+                for bvh in [&bvh4 as &dyn Hit, &bvh8] {
+                    let packet = bvh.hit_packet(&rays, 0.001, Float::INFINITY);
+                    for (r, rec) in rays.iter().zip(packet) {
+                        let expected = bvh.hit(r, 0.001, Float::INFINITY).map(|rec| (rec.t, rec.object_id));
+                        assert_eq!(rec.map(|rec| (rec.t, rec.object_id)), expected);
+                        match expected {
+                            Some(_) => hits += 1,
+                            None => misses += 1,
+                        }
+                    }
+                }
+            }
+        }
+        assert!(split > 0 && split < 64, "{} tiles split", split);
+        assert!(hits > 0 && misses > 0);
+    }
+}
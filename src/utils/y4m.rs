@@ -0,0 +1,84 @@
+use std::io::{self, Write};
+use super::float::Float;
+
+// raw yuv4mpeg2 stream with 4:2:0 chroma, which ffmpeg, mpv and most
+// encoders read directly
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: usize,
+    height: usize,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut out: W, width: usize, height: usize, fps: Float) -> io::Result<Y4mWriter<W>> {
+        // the frame rate is a ratio, thousandths keep fractional rates exact enough
+        let rate = (fps * 1000.0).round() as u64;
+        writeln!(out, "YUV4MPEG2 W{} H{} F{}:1000 Ip A1:1 C420jpeg", width, height, rate)?;
+        Ok(Y4mWriter { out, width, height })
+    }
+
+    // takes gamma corrected 8 bit rgb, converted with bt.601 studio range
+    pub fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()> {
+        assert_eq!(rgb.len(), self.width * self.height * 3);
+        let pixel = |x: usize, y: usize| {
+            let i = 3 * (y * self.width + x);
+            (rgb[i] as Float, rgb[i + 1] as Float, rgb[i + 2] as Float)
+        };
+
+        let mut luma = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (r, g, b) = pixel(x, y);
+                luma.push((16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8);
+            }
+        }
+
+        // chroma is averaged over each 2x2 block
+        let chroma_width = self.width.div_ceil(2);
+        let chroma_height = self.height.div_ceil(2);
+        let mut cb = Vec::with_capacity(chroma_width * chroma_height);
+        let mut cr = Vec::with_capacity(chroma_width * chroma_height);
+        for cy in 0..chroma_height {
+            for cx in 0..chroma_width {
+                let (mut r, mut g, mut b, mut n) = (0.0, 0.0, 0.0, 0.0);
+                for y in (2 * cy)..(2 * cy + 2).min(self.height) {
+                    for x in (2 * cx)..(2 * cx + 2).min(self.width) {
+                        let p = pixel(x, y);
+                        r += p.0;
+                        g += p.1;
+                        b += p.2;
+                        n += 1.0;
+                    }
+                }
+                let (r, g, b) = (r / n, g / n, b / n);
+                cb.push((128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8);
+                cr.push((128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8);
+            }
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&luma)?;
+        self.out.write_all(&cb)?;
+        self.out.write_all(&cr)?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Y4mWriter;
+
+    // white, black and red in a row of odd width, so the last chroma
+    // sample only averages one pixel
+    #[test]
+    fn header_and_frame_bytes() {
+        let mut out = Vec::new();
+        let mut writer = Y4mWriter::new(&mut out, 3, 1, 29.97).unwrap();
+        writer.write_frame(&[255, 255, 255, 0, 0, 0, 255, 0, 0]).unwrap();
+
+        let mut expected = b"YUV4MPEG2 W3 H1 F29970:1000 Ip A1:1 C420jpeg\nFRAME\n".to_vec();
+        // luma, then cb and cr for the two chroma columns
+        expected.extend_from_slice(&[235, 16, 82, 128, 90, 128, 240]);
+        assert_eq!(out, expected);
+    }
+}